@@ -0,0 +1,91 @@
+//! Async variant of MineSweeperClient built on tokio
+
+use crate::request::{self, Request};
+use crate::{
+    apply_resumed, choose_version, connection_dropped, decode_reply, session_token, ClientError,
+    Game, Result,
+};
+use protocol::frame;
+use protocol::v3::{ClientMsg, ServerMsg, MAX_BYTES};
+use protocol::Message;
+use std::net::SocketAddr;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+/// A MineSweeper client that talks to the server without blocking the calling thread
+#[derive(Debug)]
+pub struct AsyncMineSweeperClient {
+    socket: TcpStream,
+    /// Server to reconnect to when the connection drops
+    addr: SocketAddr,
+    /// Token to resume the session with, None if the server can not resume sessions
+    session: Option<u64>,
+    pub game: Game,
+}
+impl AsyncMineSweeperClient {
+    /// Connects to a server and negotiates the protocol version
+    pub async fn connect<A: ToSocketAddrs>(server_addr: A) -> Result<Self> {
+        let mut socket = TcpStream::connect(server_addr).await?;
+        let session = Self::handshake(&mut socket).await?;
+
+        Ok(Self {
+            addr: socket.peer_addr()?,
+            socket,
+            session,
+            game: Game::default(),
+        })
+    }
+
+    /// Negotiates the protocol version and returns the session token if the server gave one
+    async fn handshake(socket: &mut TcpStream) -> Result<Option<u64>> {
+        let versions = Self::send_message(socket, ClientMsg::GetVersions()).await?;
+        let version = choose_version(versions)?;
+        let reply = Self::send_message(socket, ClientMsg::SetVersion(version)).await?;
+        session_token(reply)
+    }
+
+    /// Opens a new connection and resumes the session on it, continuing the game
+    pub async fn reconnect(&mut self) -> Result<()> {
+        let token = self
+            .session
+            .ok_or(ClientError::InvalidState("Server can not resume sessions"))?;
+        let mut socket = TcpStream::connect(self.addr).await?;
+        let fresh = Self::handshake(&mut socket).await?;
+        let reply = Self::send_message(&mut socket, ClientMsg::Resume(token)).await;
+        self.socket = socket;
+        apply_resumed(&mut self.game, &mut self.session, fresh, reply)
+    }
+
+    request::game_methods!(async; .await);
+
+    /// Sends a request and applies the reply to the game
+    async fn send<T>(&mut self, request: Request<T>) -> Result<T> {
+        let reply = self.request(&request.msg).await?;
+        request.apply(&mut self.game, reply)
+    }
+
+    /// Sends a message, resuming the session on a new connection once if the old one dropped
+    async fn request(&mut self, message: &ClientMsg) -> Result<ServerMsg> {
+        let bytes = message.to_frame()?;
+        match Self::exchange(&mut self.socket, &bytes).await {
+            Err(err) if self.session.is_some() && connection_dropped(&err) => {
+                self.reconnect().await?;
+                Self::exchange(&mut self.socket, &bytes).await
+            }
+            reply => reply,
+        }
+    }
+
+    /// Sends a message on a socket and reads the reply
+    async fn send_message(socket: &mut TcpStream, message: ClientMsg) -> Result<ServerMsg> {
+        Self::exchange(socket, &message.to_frame()?).await
+    }
+
+    /// Writes an encoded message and reads the reply, error replies are returned as ClientError::Server
+    async fn exchange(socket: &mut TcpStream, request: &[u8]) -> Result<ServerMsg> {
+        socket.write_all(request).await?;
+
+        let frame = frame::read_frame_async(socket, MAX_BYTES).await?;
+        decode_reply(frame)
+    }
+}
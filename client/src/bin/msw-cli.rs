@@ -0,0 +1,328 @@
+//! Scriptable client that prints every result as a line of JSON
+//!
+//! A game has to outlive a single command, so the connection to the server is held either by
+//! `msw-cli daemon`, which takes commands from other `msw-cli` invocations over a unix socket,
+//! or by `msw-cli stdin`, which reads one command per line.
+
+use clap::{Parser, Subcommand};
+use client::{Board, Cell, ClientError, GameOptions, MineSweeperClient, State};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+/// Command line of msw-cli, also used to parse the commands sent to the daemon or read from stdin
+#[derive(Parser)]
+#[command(name = "msw-cli", about = "Drives a MineSweeper server from scripts")]
+struct Args {
+    /// Server the daemon or stdin stream connects to
+    #[arg(
+        short,
+        long,
+        env = "MINESWEEPER_SERVER",
+        default_value = "127.0.0.1:8000"
+    )]
+    server: String,
+    /// Unix socket the daemon listens on [default: msw-cli-$USER.sock in the temp directory]
+    #[arg(long, env = "MSW_SOCKET")]
+    socket: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Holds a connection and serves the commands of other msw-cli invocations
+    Daemon,
+    /// Holds a connection and runs one command per line of stdin
+    Stdin,
+    #[command(flatten)]
+    Request(Request),
+}
+
+/// Commands made against the game
+#[derive(Subcommand)]
+enum Request {
+    /// Starts a new game, e.g. `new 30x16 99`
+    New {
+        /// Width and height as WIDTHxHEIGHT
+        #[arg(value_parser = parse_dim)]
+        dim: (usize, usize),
+        mines: usize,
+        #[arg(long)]
+        seed: Option<u64>,
+        #[arg(long)]
+        safe_neighbours: bool,
+        #[arg(long)]
+        no_guess: bool,
+    },
+    /// Reveals the cell at a column and row
+    Reveal { x: usize, y: usize },
+    /// Chords the revealed number at a column and row
+    Chord { x: usize, y: usize },
+    /// Flags the cell at a column and row
+    Flag { x: usize, y: usize },
+    /// Removes a flag or question mark from the cell at a column and row
+    Unflag { x: usize, y: usize },
+    /// Marks the cell at a column and row with a question mark
+    Question { x: usize, y: usize },
+    /// Asks the server for the game time
+    Time,
+    /// Prints the game without asking the server
+    State,
+    /// Ends the current game
+    Close,
+    /// Stops the daemon or the stdin stream
+    Stop,
+}
+
+/// Parses board dimensions written as WIDTHxHEIGHT
+fn parse_dim(value: &str) -> Result<(usize, usize), String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| String::from("expected WIDTHxHEIGHT"))?;
+    let parse = |n: &str| n.parse::<usize>().map_err(|err| err.to_string());
+    Ok((parse(width)?, parse(height)?))
+}
+
+/// Runs a request and returns the line of JSON reporting its outcome
+fn execute(client: &mut MineSweeperClient, request: Request) -> Value {
+    let result = match request {
+        Request::New {
+            dim,
+            mines,
+            seed,
+            safe_neighbours,
+            no_guess,
+        } => client.new_game(
+            dim,
+            mines,
+            GameOptions {
+                seed,
+                safe_neighbours,
+                no_guess,
+            },
+        ),
+        Request::Reveal { x, y } => client.reveal_at(x, y),
+        Request::Chord { x, y } => client.chord_at(x, y),
+        Request::Flag { x, y } => client.flag_at(x, y),
+        Request::Unflag { x, y } => client.unflag_at(x, y),
+        Request::Question { x, y } => client.question_at(x, y),
+        Request::Time => client.get_time().map(|_| ()),
+        Request::State | Request::Stop => Ok(()),
+        Request::Close => client.close_game(),
+    };
+    match result {
+        Ok(()) => game_json(client),
+        Err(err) => error_json(&err),
+    }
+}
+
+/// The game as JSON, rows of the board are strings with one character per cell
+fn game_json(client: &MineSweeperClient) -> Value {
+    let game = &client.game;
+    json!({
+        "ok": true,
+        "state": state_name(game.state),
+        "time": game.time,
+        "seed": game.seed,
+        "mines_remaining": game.mines_remaining,
+        "width": game.board.as_ref().map(|b| b.dim.0),
+        "height": game.board.as_ref().map(|b| b.dim.1),
+        "board": game.board.as_ref().map(board_rows),
+    })
+}
+
+/// A failed request as JSON, code is only set for errors sent by the server
+fn error_json(err: &ClientError) -> Value {
+    let code = match err {
+        ClientError::Server(code) => Some(code.code()),
+        _ => None,
+    };
+    json!({ "ok": false, "error": err.to_string(), "code": code })
+}
+
+/// Any failure outside of a request, such as a command that could not be parsed
+fn failure_json(message: impl ToString) -> Value {
+    json!({ "ok": false, "error": message.to_string(), "code": null })
+}
+
+fn state_name(state: State) -> &'static str {
+    match state {
+        State::Playing => "playing",
+        State::Idle => "idle",
+        State::Lost => "lost",
+        State::Won => "won",
+    }
+}
+
+/// One string per row: `#` hidden, `F` flag, `?` question mark, `*` mine,
+/// `X` exploded mine, `.` empty and `1`..`8` numbers
+fn board_rows(board: &Board) -> Vec<String> {
+    board
+        .cells
+        .chunks(board.dim.0)
+        .map(|row| {
+            row.iter()
+                .map(|cell| match cell {
+                    Cell::Revealed(0) => '.',
+                    Cell::Revealed(n) => char::from(b'0' + n),
+                    Cell::Hidden => '#',
+                    Cell::Flagged => 'F',
+                    Cell::Question => '?',
+                    Cell::Mine => '*',
+                    Cell::MineExploded => 'X',
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Parses a command the same way as the command line, global options are ignored
+fn parse_request<I: IntoIterator<Item = String>>(words: I) -> Result<Request, Value> {
+    let args = std::iter::once(String::from("msw-cli")).chain(words);
+    match Args::try_parse_from(args) {
+        Ok(Args {
+            command: Command::Request(request),
+            ..
+        }) => Ok(request),
+        Ok(_) => Err(failure_json("Only game commands can be sent")),
+        Err(err) => Err(failure_json(err.to_string().trim_end())),
+    }
+}
+
+/// Connects to the server, reporting a failure as JSON
+fn connect(server: &str) -> Result<MineSweeperClient, Value> {
+    MineSweeperClient::connect(server).map_err(|err| error_json(&err))
+}
+
+/// Runs one command per line of stdin, printing a line of JSON for each
+fn run_stdin(server: &str) -> Result<(), Value> {
+    let mut client = connect(server)?;
+    let mut stdout = io::stdout().lock();
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(failure_json)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match parse_request(line.split_whitespace().map(String::from)) {
+            Ok(Request::Stop) => return Ok(()),
+            Ok(request) => execute(&mut client, request),
+            Err(reply) => reply,
+        };
+        writeln!(stdout, "{}", reply).map_err(failure_json)?;
+        stdout.flush().map_err(failure_json)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+mod daemon {
+    //! Unix socket carrying one command per connection, sent as a JSON array of its arguments
+
+    use super::*;
+    use std::io::{BufReader, Read};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+
+    /// Default socket path, one per user so several users can share a machine
+    pub fn default_socket() -> PathBuf {
+        let user = std::env::var("USER").unwrap_or_else(|_| String::from("default"));
+        std::env::temp_dir().join(format!("msw-cli-{}.sock", user))
+    }
+
+    /// Serves commands until one of them is `stop`
+    pub fn run(server: &str, socket: &Path) -> Result<(), Value> {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(failure_json(format!(
+                "A daemon is already listening on {}",
+                socket.display()
+            )));
+        }
+        // Nobody answered so the file is left over from a daemon that did not stop cleanly
+        let _ = std::fs::remove_file(socket);
+
+        let mut client = connect(server)?;
+        let listener = UnixListener::bind(socket).map_err(failure_json)?;
+        println!("{}", json!({ "ok": true, "socket": socket }));
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            match serve(&mut client, stream) {
+                Ok(true) => break,
+                Ok(false) => (),
+                Err(err) => eprintln!("Failed to serve a command: {}", err),
+            }
+        }
+        let _ = std::fs::remove_file(socket);
+        Ok(())
+    }
+
+    /// Runs the command sent on a connection, returns true if the daemon should stop
+    fn serve(client: &mut MineSweeperClient, mut stream: UnixStream) -> io::Result<bool> {
+        let mut line = String::new();
+        BufReader::new(&mut stream).read_line(&mut line)?;
+        let (reply, stop) = match serde_json::from_str::<Vec<String>>(&line) {
+            Ok(words) => match parse_request(words) {
+                Ok(Request::Stop) => (json!({ "ok": true }), true),
+                Ok(request) => (execute(client, request), false),
+                Err(reply) => (reply, false),
+            },
+            Err(err) => (failure_json(err), false),
+        };
+        writeln!(stream, "{}", reply)?;
+        Ok(stop)
+    }
+
+    /// Sends a command to the daemon and returns its reply
+    pub fn send(socket: &Path, words: Vec<String>) -> Result<Value, Value> {
+        let mut stream = UnixStream::connect(socket).map_err(|err| {
+            failure_json(format!(
+                "No daemon listening on {}, start one with `msw-cli daemon`: {}",
+                socket.display(),
+                err
+            ))
+        })?;
+        writeln!(stream, "{}", json!(words)).map_err(failure_json)?;
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).map_err(failure_json)?;
+        serde_json::from_str(&reply).map_err(failure_json)
+    }
+}
+
+fn run(args: Args) -> Result<Value, Value> {
+    match args.command {
+        Command::Stdin => run_stdin(&args.server).map(|()| Value::Null),
+        #[cfg(unix)]
+        Command::Daemon => {
+            let socket = args.socket.unwrap_or_else(daemon::default_socket);
+            daemon::run(&args.server, &socket).map(|()| Value::Null)
+        }
+        #[cfg(unix)]
+        Command::Request(_) => {
+            let socket = args.socket.unwrap_or_else(daemon::default_socket);
+            // The daemon parses the arguments again, global options included
+            daemon::send(&socket, std::env::args().skip(1).collect())
+        }
+        #[cfg(not(unix))]
+        Command::Daemon | Command::Request(_) => Err(failure_json(
+            "The daemon needs unix sockets, use `msw-cli stdin` instead",
+        )),
+    }
+}
+
+fn main() {
+    let reply = run(Args::parse());
+    let failed = match reply {
+        Ok(Value::Null) => false,
+        Ok(ref reply) => reply["ok"] == false,
+        Err(_) => true,
+    };
+    match reply {
+        Ok(Value::Null) => (),
+        Ok(reply) | Err(reply) => println!("{}", reply),
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
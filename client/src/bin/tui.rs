@@ -0,0 +1,293 @@
+//! Terminal front end for playing over SSH or anywhere else without a display
+
+use clap::Parser;
+use client::{Board, Cell, ClientError, Coord, GameOptions, MineSweeperClient, State};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::time::{Duration, Instant};
+
+/// How often the clock is refreshed while a game is running
+const TICK: Duration = Duration::from_secs(1);
+
+/// Columns used to draw a cell
+const CELL_WIDTH: u16 = 2;
+
+/// Command line options of the terminal client
+#[derive(Parser)]
+#[command(about = "Plays MineSweeper against a server in the terminal")]
+struct Args {
+    /// Server to connect to
+    #[arg(
+        short,
+        long,
+        env = "MINESWEEPER_SERVER",
+        default_value = "127.0.0.1:8000"
+    )]
+    server: String,
+    /// Board width
+    #[arg(long, default_value_t = 10)]
+    width: usize,
+    /// Board height
+    #[arg(long, default_value_t = 10)]
+    height: usize,
+    /// Number of mines
+    #[arg(short, long, default_value_t = 10)]
+    mines: usize,
+    /// Seed to replay a board with
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Guarantee the first reveal opens an area
+    #[arg(long)]
+    safe_neighbours: bool,
+    /// Only generate boards that can be solved without guessing
+    #[arg(long)]
+    no_guess: bool,
+}
+
+struct App {
+    client: MineSweeperClient,
+    server: String,
+    cursor: Coord,
+    dim: (usize, usize),
+    mine_count: usize,
+    options: GameOptions,
+    /// Error of the last failed action, shown until the next action
+    error: Option<String>,
+    last_tick: Instant,
+}
+impl App {
+    /// Starts a new game with the configured size and options
+    fn new_game(&mut self) {
+        let result = self
+            .client
+            .new_game(self.dim, self.mine_count, self.options);
+        self.cursor = Coord::new(self.dim.0 / 2, self.dim.1 / 2);
+        self.show(result);
+    }
+
+    /// Records the outcome of an action for the status line
+    fn show(&mut self, result: client::Result<()>) {
+        self.error = result.err().map(|err| err.to_string());
+    }
+
+    /// Moves the cursor, staying on the board
+    fn move_cursor(&mut self, dx: isize, dy: isize) {
+        let x = self.cursor.x.saturating_add_signed(dx);
+        let y = self.cursor.y.saturating_add_signed(dy);
+        if Coord::new(x, y).in_bounds(self.dim) {
+            self.cursor = Coord::new(x, y);
+        }
+    }
+
+    /// Reveals the cell under the cursor, or chords it if it is already revealed
+    fn reveal(&mut self) {
+        let Coord { x, y } = self.cursor;
+        let result = match self.cell() {
+            Some(Cell::Revealed(_)) => self.client.chord_at(x, y),
+            _ => self.client.reveal_at(x, y),
+        };
+        self.show(result);
+    }
+
+    /// Chords the cell under the cursor
+    fn chord(&mut self) {
+        let Coord { x, y } = self.cursor;
+        let result = self.client.chord_at(x, y);
+        self.show(result);
+    }
+
+    /// Cycles the cell under the cursor through flag, question mark and back, like a right click
+    fn cycle_mark(&mut self) {
+        let Coord { x, y } = self.cursor;
+        let result = match self.cell() {
+            Some(Cell::Hidden) => self.client.flag_at(x, y),
+            Some(Cell::Flagged) => self.client.question_at(x, y),
+            Some(Cell::Question) => self.client.unflag_at(x, y),
+            _ => return,
+        };
+        self.show(result);
+    }
+
+    /// The cell under the cursor, None without a board
+    fn cell(&self) -> Option<&Cell> {
+        self.client.game.board.as_ref()?.cell_at(self.cursor)
+    }
+
+    /// Polls the clock once a second while the game is running
+    fn tick(&mut self) {
+        if self.last_tick.elapsed() < TICK {
+            return;
+        }
+        self.last_tick = Instant::now();
+        if self.client.game.state == State::Playing {
+            // A failing clock should not hide the error of the last move
+            if let Err(err @ ClientError::Io(_)) = self.client.get_time() {
+                self.error = Some(err.to_string());
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [status, board_area, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let game = &self.client.game;
+        let status_line = match self.error {
+            Some(ref error) => Line::styled(error.as_str(), Style::new().fg(Color::Red)),
+            None => Line::raw(format!(
+                "{:?}  Time: {}  Mines: {}  Seed: {}  Server: {}",
+                game.state,
+                game.time,
+                game.mines_remaining,
+                game.seed.map(|s| s.to_string()).unwrap_or_default(),
+                self.server
+            )),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+
+        if let Some(ref board) = game.board {
+            self.draw_board(frame, board, board_area);
+        }
+
+        frame.render_widget(
+            Paragraph::new("arrows/hjkl move  space reveal  f flag  c chord  n new game  q quit")
+                .style(Style::new().add_modifier(Modifier::DIM)),
+            help,
+        );
+    }
+
+    /// Draws the part of the board around the cursor that fits into `area`
+    fn draw_board(&self, frame: &mut Frame, board: &Board, area: Rect) {
+        let block = Block::bordered().title(format!(" {}x{} ", board.dim.0, board.dim.1));
+        let inner = block.inner(area);
+        let columns = (inner.width / CELL_WIDTH).max(1) as usize;
+        let rows = inner.height.max(1) as usize;
+        let left = scroll(self.cursor.x, columns, board.dim.0);
+        let top = scroll(self.cursor.y, rows, board.dim.1);
+
+        let lines: Vec<Line> = (top..board.dim.1.min(top + rows))
+            .map(|y| {
+                (left..board.dim.0.min(left + columns))
+                    .map(|x| {
+                        let coord = Coord::new(x, y);
+                        let mut span = match board.cell_at(coord) {
+                            Some(cell) => cell_span(cell),
+                            None => Span::raw(" "),
+                        };
+                        if coord == self.cursor {
+                            span = span.patch_style(Modifier::REVERSED);
+                        }
+                        span.content =
+                            format!("{:>width$}", span.content, width = CELL_WIDTH as usize).into();
+                        span
+                    })
+                    .collect()
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
+
+/// First row or column to draw so the cursor stays visible when the board does not fit
+fn scroll(cursor: usize, visible: usize, len: usize) -> usize {
+    if len <= visible || cursor < visible / 2 {
+        0
+    } else {
+        (cursor - visible / 2).min(len - visible)
+    }
+}
+
+/// How a cell is drawn
+fn cell_span(cell: &Cell) -> Span<'static> {
+    match cell {
+        Cell::Revealed(0) => Span::raw("."),
+        Cell::Revealed(n) => {
+            let color = match n {
+                1 => Color::Blue,
+                2 => Color::Green,
+                3 => Color::Red,
+                4 => Color::Magenta,
+                5 => Color::Yellow,
+                6 => Color::Cyan,
+                _ => Color::Gray,
+            };
+            Span::styled(n.to_string(), Style::new().fg(color))
+        }
+        Cell::Hidden => Span::raw("#"),
+        Cell::Flagged => Span::styled("F", Style::new().fg(Color::Red)),
+        Cell::Question => Span::styled("?", Style::new().fg(Color::Yellow)),
+        Cell::Mine => Span::raw("*"),
+        Cell::MineExploded => Span::styled("*", Style::new().fg(Color::White).bg(Color::Red)),
+    }
+}
+
+/// Handles key presses until the player quits
+fn run(terminal: &mut DefaultTerminal, app: &mut App) -> std::io::Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        app.tick();
+        if !event::poll(TICK)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Left | KeyCode::Char('h') => app.move_cursor(-1, 0),
+            KeyCode::Right | KeyCode::Char('l') => app.move_cursor(1, 0),
+            KeyCode::Up | KeyCode::Char('k') => app.move_cursor(0, -1),
+            KeyCode::Down | KeyCode::Char('j') => app.move_cursor(0, 1),
+            KeyCode::Char(' ') | KeyCode::Enter => app.reveal(),
+            KeyCode::Char('f') => app.cycle_mark(),
+            KeyCode::Char('c') => app.chord(),
+            KeyCode::Char('n') => app.new_game(),
+            _ => (),
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let client = match MineSweeperClient::connect(&args.server) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("Failed to connect to {}: {}", args.server, err);
+            std::process::exit(1);
+        }
+    };
+    let mut app = App {
+        client,
+        server: args.server,
+        cursor: Coord::new(0, 0),
+        dim: (args.width, args.height),
+        mine_count: args.mines,
+        options: GameOptions {
+            seed: args.seed,
+            safe_neighbours: args.safe_neighbours,
+            no_guess: args.no_guess,
+        },
+        error: None,
+        last_tick: Instant::now(),
+    };
+    app.new_game();
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut app);
+    ratatui::restore();
+    if let Err(err) = result {
+        eprintln!("Terminal error: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,41 @@
+//! Cell coordinates and their conversion to the linear indices used on the wire
+
+/// Position of a cell, x counts columns from the left and y rows from the top
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Coord {
+    pub x: usize,
+    pub y: usize,
+}
+impl Coord {
+    pub fn new(x: usize, y: usize) -> Self {
+        Self { x, y }
+    }
+
+    /// Returns true if the coordinate lies on a board of `dim`
+    pub fn in_bounds(self, dim: (usize, usize)) -> bool {
+        self.x < dim.0 && self.y < dim.1
+    }
+
+    /// Linear index of the cell on a board of `dim`, None if it lies outside of the board
+    pub fn to_index(self, dim: (usize, usize)) -> Option<usize> {
+        self.in_bounds(dim).then(|| self.x + self.y * dim.0)
+    }
+
+    /// Coordinate of a linear index on a board of `dim`, None if it lies outside of the board
+    pub fn from_index(index: usize, dim: (usize, usize)) -> Option<Self> {
+        (index < dim.0 * dim.1).then(|| Self::new(index % dim.0, index / dim.0))
+    }
+
+    /// Iterates over the up to 8 surrounding cells which lie on a board of `dim`
+    pub fn neighbours(self, dim: (usize, usize)) -> impl Iterator<Item = Coord> {
+        let xs = self.x.saturating_sub(1)..=self.x.saturating_add(1);
+        let ys = self.y.saturating_sub(1)..=self.y.saturating_add(1);
+        ys.flat_map(move |y| xs.clone().map(move |x| Coord::new(x, y)))
+            .filter(move |c| *c != self && c.in_bounds(dim))
+    }
+}
+impl From<(usize, usize)> for Coord {
+    fn from((x, y): (usize, usize)) -> Self {
+        Self::new(x, y)
+    }
+}
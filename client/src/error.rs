@@ -0,0 +1,60 @@
+//! Errors returned by MineSweeperClient
+
+use protocol::ErrorCode;
+use std::{fmt, io};
+
+/// Result of every request made by MineSweeperClient
+pub type Result<T, E = ClientError> = std::result::Result<T, E>;
+
+/// Why a request to the server failed
+#[derive(Debug)]
+pub enum ClientError {
+    /// The connection to the server failed or was closed
+    Io(io::Error),
+    /// A message could not be encoded or decoded, or the server sent a reply that makes no sense
+    Protocol(anyhow::Error),
+    /// The server rejected the request
+    Server(ErrorCode),
+    /// The request can not be made in the client's current state, nothing was sent
+    InvalidState(&'static str),
+}
+impl ClientError {
+    /// Error for a reply the request does not allow
+    pub(crate) fn unexpected(request: &str) -> Self {
+        ClientError::Protocol(anyhow::anyhow!("Unexpected reply to {}", request))
+    }
+}
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(err) => write!(f, "Connection failed: {}", err),
+            ClientError::Protocol(err) => write!(f, "Protocol error: {:#}", err),
+            ClientError::Server(code) => write!(f, "{}", code),
+            ClientError::InvalidState(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Io(err) => Some(err),
+            ClientError::Protocol(err) => Some(err.as_ref()),
+            ClientError::Server(_) | ClientError::InvalidState(_) => None,
+        }
+    }
+}
+impl From<io::Error> for ClientError {
+    fn from(value: io::Error) -> Self {
+        ClientError::Io(value)
+    }
+}
+impl From<anyhow::Error> for ClientError {
+    fn from(value: anyhow::Error) -> Self {
+        ClientError::Protocol(value)
+    }
+}
+impl From<ErrorCode> for ClientError {
+    fn from(value: ErrorCode) -> Self {
+        ClientError::Server(value)
+    }
+}
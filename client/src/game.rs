@@ -0,0 +1,257 @@
+//! Game state kept by both clients and how the server's replies change it
+
+use crate::{Analysis, Board, Cell, ClientError, Coord, Result, State};
+use anyhow::anyhow;
+use protocol::v3::{
+    BoardState, GameStatus, Reveals, ServerMsg, CELL_FLAGGED, CELL_HIDDEN, CELL_MINE, CELL_QUESTION,
+};
+
+/// What the client knows about the game being played
+#[derive(Clone, Debug)]
+pub struct Game {
+    pub state: State,
+    pub board: Option<Board>,
+    /// Last game time reported by the server in seconds
+    pub time: String,
+    /// Seed of the current game, pass it in GameOptions to replay the same board
+    pub seed: Option<u64>,
+    /// Mines not yet accounted for by flags, as counted by the server
+    pub mines_remaining: i32,
+}
+impl Default for Game {
+    fn default() -> Self {
+        Self {
+            state: State::Idle,
+            board: None,
+            time: String::new(),
+            seed: None,
+            mines_remaining: 0,
+        }
+    }
+}
+impl Game {
+    /// Returns the board moves apply to, failing if there is no game being played
+    pub fn playing_board(&self) -> Result<&Board> {
+        match (&self.board, &self.state) {
+            (Some(board), State::Playing) => Ok(board),
+            (Some(_), _) => Err(ClientError::InvalidState("Game is already over")),
+            (None, _) => Err(ClientError::InvalidState("No game is in progress")),
+        }
+    }
+
+    /// Converts a coordinate on the current board without asking the server
+    pub fn index_at(&self, x: usize, y: usize) -> Result<usize> {
+        self.playing_board()?
+            .index(Coord::new(x, y))
+            .ok_or(ClientError::InvalidState(
+                "Coordinate is outside of the board",
+            ))
+    }
+
+    /// Analyses the board being played using the mine count reported by the server
+    pub fn analyse(&self) -> Result<Analysis> {
+        let board = self.playing_board()?;
+        usize::try_from(self.mines_remaining)
+            .ok()
+            .and_then(|mines_left| board.analyse(mines_left).ok())
+            .ok_or(ClientError::InvalidState(
+                "The revealed numbers, flags and mine count contradict each other",
+            ))
+    }
+
+    /// Starts the game the server created in reply to NewGame
+    pub(crate) fn apply_new_game(
+        &mut self,
+        dim: (usize, usize),
+        mine_count: usize,
+        reply: ServerMsg,
+    ) -> Result<()> {
+        match reply {
+            ServerMsg::GameCreated(seed) => {
+                self.board = Some(Board::new(dim));
+                self.state = State::Playing;
+                self.seed = Some(seed);
+                self.mines_remaining = mine_count as i32;
+                self.time = String::new();
+                Ok(())
+            }
+            _ => Err(ClientError::unexpected("NewGame")),
+        }
+    }
+
+    /// Drops the game once the server accepted CloseGame
+    pub(crate) fn apply_close_game(&mut self, reply: ServerMsg) -> Result<()> {
+        match reply {
+            ServerMsg::Accepted() => {
+                self.board = None;
+                self.state = State::Idle;
+                Ok(())
+            }
+            _ => Err(ClientError::unexpected("CloseGame")),
+        }
+    }
+
+    /// Applies the cells revealed by a move, ending the game if it was won or lost
+    pub(crate) fn apply_move(&mut self, reply: ServerMsg) -> Result<()> {
+        let Some(ref mut board) = self.board else {
+            return Err(ClientError::InvalidState("No game is in progress"));
+        };
+        let len = board.cells.len();
+        match reply {
+            ServerMsg::RevealCells(cells) => {
+                check_reveals(&cells, len)?;
+                board.reveal_cells(&cells);
+            }
+            ServerMsg::GameWin(time, cells) => {
+                check_reveals(&cells, len)?;
+                board.reveal_cells(&cells);
+                board.reveal_all_as_mines();
+                self.state = State::Won;
+                self.time = time;
+            }
+            ServerMsg::GameLoss(time, mines) => {
+                check_in_board(mines.iter().map(|i| *i as usize), len)?;
+                board.show_mines(&mines);
+                self.state = State::Lost;
+                self.time = time;
+            }
+            _ => return Err(ClientError::unexpected("a move")),
+        }
+        Ok(())
+    }
+
+    /// Shows a mark once the server accepted it
+    pub(crate) fn apply_mark(&mut self, index: usize, cell: Cell, reply: ServerMsg) -> Result<()> {
+        match reply {
+            ServerMsg::MinesRemaining(remaining) => {
+                if let Some(target) = self.board.as_mut().and_then(|b| b.cells.get_mut(index)) {
+                    *target = cell;
+                }
+                self.mines_remaining = remaining;
+                Ok(())
+            }
+            _ => Err(ClientError::unexpected("a mark")),
+        }
+    }
+
+    /// Replaces the game with the one the server kept for a resumed session
+    pub(crate) fn apply_resumed(&mut self, reply: ServerMsg) -> Result<()> {
+        match reply {
+            ServerMsg::Resumed(None) => {
+                *self = Game::default();
+                Ok(())
+            }
+            ServerMsg::Resumed(Some(state)) => {
+                *self = Game::from_state(state)?;
+                Ok(())
+            }
+            _ => Err(ClientError::unexpected("Resume")),
+        }
+    }
+
+    /// Rebuilds a game from the full state sent by the server
+    fn from_state(state: BoardState) -> Result<Game> {
+        let dim = (state.width as usize, state.height as usize);
+        if state.cells.len() != dim.0 * dim.1 {
+            return Err(ClientError::Protocol(anyhow!(
+                "Server sent {} cells for a {}x{} board",
+                state.cells.len(),
+                dim.0,
+                dim.1
+            )));
+        }
+        let cells = state
+            .cells
+            .iter()
+            .map(|value| match *value {
+                value @ 0..=8 => Ok(Cell::Revealed(value)),
+                CELL_HIDDEN => Ok(Cell::Hidden),
+                CELL_FLAGGED => Ok(Cell::Flagged),
+                CELL_QUESTION => Ok(Cell::Question),
+                CELL_MINE if state.status == GameStatus::Lost => Ok(Cell::MineExploded),
+                CELL_MINE => Ok(Cell::Mine),
+                value => Err(ClientError::Protocol(anyhow!(
+                    "Server sent unknown cell value {}",
+                    value
+                ))),
+            })
+            .collect::<Result<Vec<Cell>>>()?;
+        Ok(Game {
+            state: match state.status {
+                GameStatus::Playing => State::Playing,
+                GameStatus::Won => State::Won,
+                GameStatus::Lost => State::Lost,
+            },
+            board: Some(Board { dim, cells }),
+            time: state.time,
+            seed: Some(state.seed),
+            mines_remaining: state.mines_remaining,
+        })
+    }
+
+    /// Records the time the server reported in reply to GetTime
+    pub(crate) fn apply_time(&mut self, reply: ServerMsg) -> Result<String> {
+        match reply {
+            ServerMsg::Time(time) => {
+                self.time = time.clone();
+                Ok(time)
+            }
+            _ => Err(ClientError::unexpected("GetTime")),
+        }
+    }
+}
+
+/// Fails if the server revealed a cell outside of a board with `len` cells or a value no cell can have
+fn check_reveals(cells: &Reveals, len: usize) -> Result<()> {
+    check_in_board(cells.iter().map(|(i, _)| i), len)?;
+    if let Some((_, value)) = cells.iter().find(|(_, v)| *v > 8) {
+        return Err(ClientError::Protocol(anyhow!(
+            "Server revealed a cell with {} neighbouring mines",
+            value
+        )));
+    }
+    Ok(())
+}
+
+/// Fails if the server sent an index outside of a board with `len` cells
+fn check_in_board(mut indices: impl Iterator<Item = usize>, len: usize) -> Result<()> {
+    if indices.any(|i| i >= len) {
+        return Err(ClientError::Protocol(anyhow!(
+            "Server sent a cell outside of the board"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing(dim: (usize, usize)) -> Game {
+        let mut game = Game::default();
+        game.apply_new_game(dim, 1, ServerMsg::GameCreated(0))
+            .unwrap();
+        game
+    }
+
+    #[test]
+    fn rejects_reveals_no_cell_can_have() {
+        let mut game = playing((3, 3));
+        let reply = ServerMsg::RevealCells([(4, 13)].into_iter().collect());
+        assert!(matches!(
+            game.apply_move(reply),
+            Err(ClientError::Protocol(_))
+        ));
+        let reply = ServerMsg::GameWin(String::new(), [(9, 1)].into_iter().collect());
+        assert!(matches!(
+            game.apply_move(reply),
+            Err(ClientError::Protocol(_))
+        ));
+        assert_eq!(game.board.as_ref().unwrap().cells[4], Cell::Hidden);
+        assert_eq!(game.state, State::Playing);
+
+        let reply = ServerMsg::RevealCells([(4, 8)].into_iter().collect());
+        game.apply_move(reply).unwrap();
+        assert_eq!(game.board.unwrap().cells[4], Cell::Revealed(8));
+    }
+}
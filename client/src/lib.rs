@@ -1,26 +1,43 @@
 //! Defines a client which can interact with MineSweeper server
-mod protocol;
-mod protocol_v2;
-mod zip;
+use anyhow::anyhow;
+use protocol::frame::{self, FrameHeader};
+use protocol::v3::{Bytes, ClientMsg, Reveals, ServerMsg, MAX_BYTES};
+use protocol::Message;
 
-use anyhow::Result;
-use protocol_v2::{Bytes, ClientMsg, ServerMsg, MAX_BYTES};
+#[cfg(feature = "tokio")]
+mod async_client;
+mod coord;
+mod error;
+mod game;
+mod request;
+
+#[cfg(feature = "tokio")]
+pub use async_client::AsyncMineSweeperClient;
+pub use coord::Coord;
+pub use error::{ClientError, Result};
+pub use game::Game;
+pub use protocol::v3::GameOptions;
+pub use protocol::ErrorCode;
+use request::Request;
+pub use solver::{Analysis, Contradiction};
 use std::{
-    io::{Read, Write},
-    net::{TcpStream, ToSocketAddrs},
+    io::{self, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
 };
 
 /// Represents an individual MineSweeper cell's state
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum Cell {
     Revealed(u8),
-    Hidden(bool),
+    Hidden,
+    Flagged,
+    Question,
     Mine,
     MineExploded,
 }
 
 /// Represents the games current state
-#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum State {
     Playing,
     Idle,
@@ -29,15 +46,10 @@ pub enum State {
 }
 impl State {
     pub fn should_display(&self) -> bool {
-        match self {
-            State::Playing | State::Lost | State::Won => {
-                true
-            },
-            _ => false,
-        }
+        matches!(self, State::Playing | State::Lost | State::Won)
     }
 }
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Board {
     pub dim: (usize, usize),
     pub cells: Vec<Cell>,
@@ -46,140 +58,233 @@ impl Board {
     pub fn new(dim: (usize, usize)) -> Board {
         Board {
             dim,
-            cells: vec![Cell::Hidden(false); dim.0 * dim.1],
+            cells: vec![Cell::Hidden; dim.0 * dim.1],
         }
     }
-    pub fn reveal_cells(&mut self, cells: &Vec<u8>) {
-        assert!(
-            cells.len() == self.cells.len(),
-            "Too many cells were provided"
-        );
-        for (i, v) in cells.iter().enumerate() {
-            match v {
-                0..=8 => self.cells[i] = Cell::Revealed(*v),
-                _ => (),
-            }
+    /// Applies the cells revealed by a move
+    pub fn reveal_cells(&mut self, cells: &Reveals) {
+        for (i, v) in cells.iter() {
+            assert!(
+                i < self.cells.len(),
+                "Revealed cell is outside of the board"
+            );
+            self.cells[i] = Cell::Revealed(v);
         }
     }
 
-    /// Flags a cell for convenience
-    pub fn flag_cell(&mut self, index: usize) {
-        assert!(index < self.cells.len(), "Index provided is out of range");
-        if let Cell::Hidden(ref mut val) = self.cells[index] {
-            *val = !*val;
-        }
+    /// Returns true if the cell has not been revealed yet, whether or not it is marked
+    pub fn is_hidden(&self, index: usize) -> bool {
+        matches!(
+            self.cells[index],
+            Cell::Hidden | Cell::Flagged | Cell::Question
+        )
+    }
+
+    /// Index of the cell at a coordinate, None if it lies outside of the board
+    pub fn index(&self, coord: Coord) -> Option<usize> {
+        coord.to_index(self.dim)
+    }
+
+    /// Coordinate of the cell at an index, None if it lies outside of the board
+    pub fn coord(&self, index: usize) -> Option<Coord> {
+        Coord::from_index(index, self.dim)
+    }
+
+    /// Returns the cell at a coordinate, None if it lies outside of the board
+    pub fn cell_at(&self, coord: Coord) -> Option<&Cell> {
+        self.index(coord).map(|i| &self.cells[i])
+    }
+
+    /// Returns the indices of the up to 8 cells surrounding a cell
+    pub fn neighbours(&self, index: usize) -> Vec<usize> {
+        let Some(coord) = self.coord(index) else {
+            return vec![];
+        };
+        coord
+            .neighbours(self.dim)
+            .filter_map(|c| self.index(c))
+            .collect()
     }
 
     /// Reveals all hidden cells as mines
     pub fn reveal_all_as_mines(&mut self) {
         for i in 0..self.cells.len() {
-            if let Cell::Hidden(_) = self.cells[i] {
+            if self.is_hidden(i) {
                 self.cells[i] = Cell::Mine;
             }
         }
     }
 
+    /// Works out which hidden cells are safe, which are mines and how likely each is a mine
+    ///
+    /// `mines_left` counts the mines not covered by flags. Flags are trusted by the analysis.
+    pub fn analyse(&self, mines_left: usize) -> Result<Analysis, Contradiction> {
+        let tiles: Vec<solver::Tile> = self
+            .cells
+            .iter()
+            .map(|cell| match cell {
+                Cell::Revealed(n) => solver::Tile::Revealed(*n),
+                Cell::Hidden | Cell::Question => solver::Tile::Hidden,
+                Cell::Flagged | Cell::Mine | Cell::MineExploded => solver::Tile::Flagged,
+            })
+            .collect();
+        solver::analyse(&tiles, self.dim.0, mines_left)
+    }
+
     /// Show Mines
-    pub fn show_mines(&mut self, mines: &Vec<u16>) {
+    pub fn show_mines(&mut self, mines: &[u32]) {
         for i in mines {
             self.cells[*i as usize] = Cell::MineExploded;
         }
     }
 }
 
+/// Protocol versions this client can speak, most preferred first
+pub const SUPPORTED_VERSIONS: [u16; 1] = [3];
+
 /// A MineSweeper client to interact with server online
 pub struct MineSweeperClient {
     socket: TcpStream,
-    error_code: u16,
-    pub state: State,
-    pub board: Option<Board>,
+    /// Server to reconnect to when the connection drops
+    addr: SocketAddr,
+    /// Token to resume the session with, None if the server can not resume sessions
+    session: Option<u64>,
+    pub game: Game,
 }
 impl MineSweeperClient {
-    /// Starts a game by connecting to server
+    /// Connects to a server and negotiates the protocol version
     pub fn connect<A: ToSocketAddrs>(server_addr: A) -> Result<Self> {
         let mut socket = TcpStream::connect(server_addr)?;
+        let session = Self::handshake(&mut socket)?;
 
-        let reply = Self::send_message(&mut socket, ClientMsg::SetVersion(2))
-            .expect("Failed opening message");
+        Ok(Self {
+            addr: socket.peer_addr()?,
+            socket,
+            session,
+            game: Game::default(),
+        })
+    }
 
-        if reply == ServerMsg::Accepted() {
-            Ok(Self {
-                socket,
-                error_code: 200,
-                state: State::Idle,
-                board: None,
-            })
-        } else {
-            panic!("Should never happen")
-        }
+    /// Negotiates the protocol version and returns the session token if the server gave one
+    fn handshake(socket: &mut TcpStream) -> Result<Option<u64>> {
+        let versions = Self::send_message(socket, ClientMsg::GetVersions())?;
+        let version = choose_version(versions)?;
+        let reply = Self::send_message(socket, ClientMsg::SetVersion(version))?;
+        session_token(reply)
     }
-    pub fn new_game(&mut self, dim: (usize, usize), mine_count: usize) {
-        let reply = Self::send_message(
-            &mut self.socket,
-            ClientMsg::NewGame(dim.0 as u8, dim.1 as u8, mine_count as u16),
-        )
-        .expect("Failed opening message");
 
-        if reply == ServerMsg::Accepted() {
-            self.board = Some(Board::new(dim));
-            self.state = State::Playing;
-        }
+    /// Opens a new connection and resumes the session on it, continuing the game
+    ///
+    /// Requests reconnect on their own when the connection fails, this is only needed to
+    /// pick a game back up without making a request.
+    pub fn reconnect(&mut self) -> Result<()> {
+        let token = self
+            .session
+            .ok_or(ClientError::InvalidState("Server can not resume sessions"))?;
+        let mut socket = TcpStream::connect(self.addr)?;
+        let fresh = Self::handshake(&mut socket)?;
+        let reply = Self::send_message(&mut socket, ClientMsg::Resume(token));
+        self.socket = socket;
+        apply_resumed(&mut self.game, &mut self.session, fresh, reply)
     }
 
-    pub fn close_game(&mut self) {
-        let reply = Self::send_message(&mut self.socket, ClientMsg::CloseGame()).unwrap();
+    request::game_methods!(;);
 
-        if reply == ServerMsg::Accepted() {
-            self.board = None;
-            self.state = State::Idle;
-        }
+    /// Sends a request and applies the reply to the game
+    fn send<T>(&mut self, request: Request<T>) -> Result<T> {
+        let reply = self.request(&request.msg)?;
+        request.apply(&mut self.game, reply)
     }
 
-    fn send_message(socket: &mut TcpStream, message: ClientMsg) -> Result<ServerMsg> {
-        let bytes = message.to_bytes()?;
-        socket.write_all(&bytes)?;
-
-        let mut buffer: Bytes = vec![0; MAX_BYTES];
-        socket.read(&mut buffer)?;
-        let reply = ServerMsg::from_bytes(&buffer)?;
-        Ok(reply)
-    }
-
-    /// Reveals a cell
-    pub fn reveal_cell(&mut self, index: usize) {
-        if let Some(ref mut board) = self.board {
-            if self.state == State::Playing {
-                let reply = Self::send_message(&mut self.socket, ClientMsg::Reveal(index as u16))
-                    .expect("Failed to send message");
-
-                match reply {
-                    ServerMsg::Error(code) => {
-                        self.error_code = code;
-                    },
-                    ServerMsg::RevealCells(cells) => {
-                        board.reveal_cells(&cells);
-                    },
-                    ServerMsg::GameWin(cells) => {
-                        board.reveal_cells(&cells);
-                        board.reveal_all_as_mines();
-                        self.state = State::Won;
-                    },
-                    ServerMsg::GameLoss(mines) => {
-                        board.show_mines(&mines);
-                        self.state = State::Lost;
-                    },
-                    _ => panic!("Invalid response received"),
-                }
+    /// Sends a message, resuming the session on a new connection once if the old one dropped
+    fn request(&mut self, message: &ClientMsg) -> Result<ServerMsg> {
+        let bytes = message.to_frame()?;
+        match Self::exchange(&mut self.socket, &bytes) {
+            Err(err) if self.session.is_some() && connection_dropped(&err) => {
+                self.reconnect()?;
+                Self::exchange(&mut self.socket, &bytes)
             }
+            reply => reply,
         }
     }
 
-    /// Flags a cell for convenience
-    pub fn flag_cell(&mut self, index: usize) {
-        if self.state == State::Playing {
-            if let Some(ref mut board) = self.board {
-                board.flag_cell(index);
+    /// Sends a message on a socket and reads the reply
+    fn send_message(socket: &mut TcpStream, message: ClientMsg) -> Result<ServerMsg> {
+        Self::exchange(socket, &message.to_frame()?)
+    }
+
+    /// Writes an encoded message and reads the reply, error replies are returned as ClientError::Server
+    fn exchange(socket: &mut TcpStream, request: &[u8]) -> Result<ServerMsg> {
+        socket.write_all(request)?;
+
+        let frame = frame::read_frame(socket, MAX_BYTES)?;
+        decode_reply(frame)
+    }
+}
+
+/// Applies the reply to Resume, carrying on with the fresh session if the old one expired
+fn apply_resumed(
+    game: &mut Game,
+    session: &mut Option<u64>,
+    fresh: Option<u64>,
+    reply: Result<ServerMsg>,
+) -> Result<()> {
+    match reply {
+        Ok(reply) => game.apply_resumed(reply),
+        Err(err) => {
+            if let ClientError::Server(ErrorCode::SessionExpired) = err {
+                // The game is lost, carry on with the session of the new connection
+                *session = fresh;
+                *game = Game::default();
             }
+            Err(err)
         }
     }
 }
+
+/// Picks the most preferred version out of the server's reply to GetVersions
+fn choose_version(reply: ServerMsg) -> Result<u16> {
+    match reply {
+        ServerMsg::Versions(versions) => SUPPORTED_VERSIONS
+            .into_iter()
+            .find(|v| versions.contains(v))
+            .ok_or_else(|| {
+                ClientError::Protocol(anyhow!(
+                    "Server only supports protocol versions {:?}",
+                    versions
+                ))
+            }),
+        _ => Err(ClientError::unexpected("GetVersions")),
+    }
+}
+
+/// Reads the session token out of the reply to SetVersion, None if sessions can not be resumed
+fn session_token(reply: ServerMsg) -> Result<Option<u64>> {
+    match reply {
+        ServerMsg::Accepted() => Ok(None),
+        ServerMsg::SessionAccepted(token) => Ok(Some(token)),
+        _ => Err(ClientError::unexpected("SetVersion")),
+    }
+}
+
+/// Returns true if a request failed because the server dropped the connection
+///
+/// A connection that sat idle for too long is closed by the server after it queued an
+/// IdleTimeout error, which the next request reads back as its reply.
+fn connection_dropped(err: &ClientError) -> bool {
+    matches!(
+        err,
+        ClientError::Io(_) | ClientError::Server(ErrorCode::IdleTimeout)
+    )
+}
+
+/// Decodes a frame read from the server, error replies are returned as ClientError::Server
+fn decode_reply(frame: Option<(FrameHeader, Bytes)>) -> Result<ServerMsg> {
+    let (header, payload) = frame.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::UnexpectedEof, "Server closed the connection")
+    })?;
+    match ServerMsg::from_frame(&header, &payload)? {
+        ServerMsg::Error(code) => Err(ClientError::Server(code)),
+        reply => Ok(reply),
+    }
+}
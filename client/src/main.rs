@@ -1,10 +1,17 @@
-use client;
+use clap::Parser;
+use client::{Analysis, AsyncMineSweeperClient, ClientError, Coord, Game, State};
 use iced::alignment::{Horizontal, Vertical};
 
 use iced::widget::{
-    button, column, container, image, mouse_area, row, text, text_input, Column, Image, Row,
+    button, checkbox, column, container, image, mouse_area, row, text, text_input, Column, Image,
+    Row,
 };
-use iced::{executor, Application, Command, Element, Length, Settings};
+use iced::{
+    executor, time, Application, Border, Color, Command, Element, Length, Settings, Subscription,
+};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 
 const IMAGES: [&str; 13] = [
     "client/images/0.png",
@@ -22,38 +29,190 @@ const IMAGES: [&str; 13] = [
     "client/images/mine_exploded.png",
 ];
 
+/// Longest gap between two clicks on a cell for them to count as a double click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Width of the frame drawn around the hinted cell
+const HINT_FRAME: u16 = 3;
+
+/// Command line options of the GUI
+#[derive(Parser)]
+#[command(about = "Plays MineSweeper against a server")]
+struct Args {
+    /// Server to connect to, can be changed before connecting
+    #[arg(
+        short,
+        long,
+        env = "MINESWEEPER_SERVER",
+        default_value = "127.0.0.1:8000"
+    )]
+    server: String,
+}
+
+/// Client shared with the requests running in the background, which take turns using it
+type SharedClient = Arc<Mutex<AsyncMineSweeperClient>>;
+
 struct MinesweeperGUI {
-    client: client::MineSweeperClient,
+    /// None until connected, kept when the connection drops as requests resume the session
+    client: Option<SharedClient>,
+    /// Address typed into the connection dialog
+    server_addr: String,
+    /// Copy of the client's game as of the last finished request, drawn while requests run
+    game: Game,
+    /// Error of the last failed action, shown until the next action
+    error: Option<String>,
     dim: (usize, usize),
     mine_count: usize,
-    speed: String,
+    options: client::GameOptions,
+    last_click: Option<(Coord, Instant)>,
+    /// Cell suggested by the last hint, cleared by the next move
+    hint: Option<Coord>,
+    /// Hints taken during the current game
+    hints: usize,
+    stats: Stats,
+    /// Whether hidden cells are tinted by how likely they are to be mines
+    heat_map: bool,
+    /// Analysis of the current board while the heat map is shown
+    analysis: Option<Analysis>,
+}
+
+/// Results of the games finished since the GUI started
+#[derive(Default)]
+struct Stats {
+    played: usize,
+    won: usize,
+    /// Games in which at least one hint was taken
+    hinted: usize,
+    /// Won games in which at least one hint was taken
+    hinted_won: usize,
+}
+impl Stats {
+    /// Counts a finished game
+    fn record(&mut self, won: bool, hints: usize) {
+        self.played += 1;
+        self.won += won as usize;
+        if hints > 0 {
+            self.hinted += 1;
+            self.hinted_won += won as usize;
+        }
+    }
+}
+
+/// Requests made to the server in the background
+#[derive(Debug, Clone)]
+enum Action {
+    Reveal(Coord),
+    Chord(Coord),
+    Flag(Coord),
+    Unflag(Coord),
+    Question(Coord),
+    NewGame((usize, usize), usize, client::GameOptions),
+    CloseGame,
+    GetTime,
 }
 
 #[derive(Debug, Clone)]
 enum Message {
-    RevealCell(usize),
-    FlagCell(usize),
+    SetServerAddr(String),
+    Connect,
+    Disconnect,
+    Connected(Result<SharedClient, Arc<ClientError>>),
+    Performed(Action, Game, Result<(), Arc<ClientError>>),
+    RevealCell(Coord),
+    ChordCell(Coord),
+    CycleMark(Coord),
     NewGame,
     SetWidth(Option<usize>),
     SetHeight(Option<usize>),
     CloseGame,
     SetMineCount(Option<usize>),
+    SetSafeNeighbours(bool),
+    SetNoGuess(bool),
+    SetSeed(String),
+    Hint,
+    SetHeatMap(bool),
+    Tick,
+}
+
+/// Carries out an action once the client is free and returns the resulting game
+async fn perform(client: SharedClient, action: Action) -> Message {
+    let mut client = client.lock().await;
+    let result = match action {
+        Action::Reveal(c) => client.reveal_at(c.x, c.y).await,
+        Action::Chord(c) => client.chord_at(c.x, c.y).await,
+        Action::Flag(c) => client.flag_at(c.x, c.y).await,
+        Action::Unflag(c) => client.unflag_at(c.x, c.y).await,
+        Action::Question(c) => client.question_at(c.x, c.y).await,
+        Action::NewGame(dim, mine_count, options) => {
+            client.new_game(dim, mine_count, options).await
+        }
+        Action::CloseGame => client.close_game().await,
+        Action::GetTime => client.get_time().await.map(|_| ()),
+    };
+    Message::Performed(action, client.game.clone(), result.map_err(Arc::new))
+}
+
+/// Connects to a server in the background
+fn connect(server_addr: String) -> Command<Message> {
+    Command::perform(AsyncMineSweeperClient::connect(server_addr), |result| {
+        Message::Connected(
+            result
+                .map(|client| Arc::new(Mutex::new(client)))
+                .map_err(Arc::new),
+        )
+    })
+}
+
+impl MinesweeperGUI {
+    /// Runs an action in the background, the GUI keeps drawing the last known game meanwhile
+    fn perform(&mut self, action: Action) -> Command<Message> {
+        match self.client {
+            Some(ref client) => Command::perform(perform(client.clone(), action), |msg| msg),
+            None => {
+                self.error = Some(String::from("Not connected to a server"));
+                Command::none()
+            }
+        }
+    }
+
+    /// Analyses the board again for the heat map, contradicting boards get no heat map
+    fn update_analysis(&mut self) {
+        self.analysis = match self.heat_map {
+            true => self.game.analyse().ok(),
+            false => None,
+        };
+    }
+
+    /// Shows an error until the next action
+    fn show_error(&mut self, err: &ClientError) {
+        self.error = Some(err.to_string());
+    }
 }
 
 impl Application for MinesweeperGUI {
     type Executor = executor::Default;
     type Message = Message;
-    type Flags = ();
+    type Flags = Args;
     type Theme = iced::theme::Theme;
 
-    fn new(_: ()) -> (Self, Command<Message>) {
+    fn new(args: Args) -> (Self, Command<Message>) {
         (
             Self {
-                client: client::MineSweeperClient::connect("127.0.0.1:8000").unwrap(),
+                client: None,
+                server_addr: args.server.clone(),
+                game: Game::default(),
+                error: None,
                 dim: (10, 10),
-                speed: String::new(),
                 mine_count: 10,
-            }, Command::none()
+                options: client::GameOptions::default(),
+                last_click: None,
+                hint: None,
+                hints: 0,
+                stats: Stats::default(),
+                heat_map: false,
+                analysis: None,
+            },
+            connect(args.server),
         )
     }
 
@@ -63,46 +222,196 @@ impl Application for MinesweeperGUI {
 
     fn update(&mut self, message: Message) -> Command<Message> {
         match message {
-            Message::RevealCell(index) => {
-                self.client.reveal_cell(index);
-            },
-            Message::FlagCell(index) => {
-                self.client.flag_cell(index);
-            },
+            Message::SetServerAddr(addr) => {
+                self.server_addr = addr;
+            }
+            Message::Connect => {
+                self.error = None;
+                return connect(self.server_addr.clone());
+            }
+            Message::Disconnect => {
+                // Dropping the client closes the connection, the server keeps the game a while
+                self.client = None;
+                self.game = Game::default();
+                self.hint = None;
+                self.analysis = None;
+            }
+            Message::Connected(Ok(client)) => {
+                self.client = Some(client);
+                self.game = Game::default();
+                self.hint = None;
+                self.analysis = None;
+            }
+            Message::Connected(Err(err)) => {
+                self.show_error(&err);
+            }
+            Message::Performed(action, game, result) => {
+                if result.is_ok() {
+                    match action {
+                        Action::NewGame(..) => {
+                            self.hint = None;
+                            self.hints = 0;
+                        }
+                        Action::GetTime => (),
+                        _ => self.hint = None,
+                    }
+                }
+                if self.game.state == State::Playing {
+                    match game.state {
+                        State::Won => self.stats.record(true, self.hints),
+                        State::Lost => self.stats.record(false, self.hints),
+                        _ => (),
+                    }
+                }
+                self.game = game;
+                if !matches!(action, Action::GetTime) {
+                    self.update_analysis();
+                }
+                match result {
+                    // The clock keeps ticking in the background so it should not hide other errors
+                    Err(err) if matches!(action, Action::GetTime) => {
+                        if let ClientError::Io(_) = *err {
+                            self.show_error(&err);
+                        }
+                    }
+                    Err(err) => self.show_error(&err),
+                    Ok(()) if matches!(action, Action::GetTime) => (),
+                    Ok(()) => self.error = None,
+                }
+            }
+            Message::RevealCell(coord) => {
+                // Double clicking a revealed number chords it
+                let now = Instant::now();
+                let double_click = matches!(
+                    self.last_click,
+                    Some((last, at)) if last == coord && now - at < DOUBLE_CLICK
+                );
+                self.last_click = Some((coord, now));
+
+                let revealed = matches!(
+                    self.game.board.as_ref().and_then(|b| b.cell_at(coord)),
+                    Some(client::Cell::Revealed(_))
+                );
+                if !revealed {
+                    return self.perform(Action::Reveal(coord));
+                } else if double_click {
+                    return self.perform(Action::Chord(coord));
+                }
+            }
+            Message::ChordCell(coord) => {
+                return self.perform(Action::Chord(coord));
+            }
+            Message::CycleMark(coord) => {
+                // Right clicks cycle a hidden cell through flag, question mark and back
+                let action = match self.game.board.as_ref().and_then(|b| b.cell_at(coord)) {
+                    Some(client::Cell::Hidden) => Action::Flag(coord),
+                    Some(client::Cell::Flagged) => Action::Question(coord),
+                    Some(client::Cell::Question) => Action::Unflag(coord),
+                    _ => return Command::none(),
+                };
+                return self.perform(action);
+            }
             Message::NewGame => {
-                self.client.new_game(self.dim, self.mine_count);
-            },
+                return self.perform(Action::NewGame(self.dim, self.mine_count, self.options));
+            }
             Message::SetWidth(w) => {
-                if w.is_some() {
-                    self.dim.0 = w.unwrap().clamp(1, 100);
+                if let Some(w) = w {
+                    self.dim.0 = w.clamp(1, 100);
                     return Command::perform(async {}, |_| Message::CloseGame);
                 }
-            },
+            }
             Message::SetHeight(h) => {
-                if h.is_some() {
-                    self.dim.1 = h.unwrap().clamp(1, 100);
+                if let Some(h) = h {
+                    self.dim.1 = h.clamp(1, 100);
                     return Command::perform(async {}, |_| Message::CloseGame);
                 }
-            },
+            }
             Message::CloseGame => {
-                self.client.close_game();
+                if self.client.is_some() {
+                    return self.perform(Action::CloseGame);
+                }
             }
             Message::SetMineCount(c) => {
-                if c.is_some() {
-                    self.mine_count = c.unwrap().clamp(1, usize::MAX);
+                if let Some(c) = c {
+                    self.mine_count = c.clamp(1, usize::MAX);
                     return Command::perform(async {}, |_| Message::CloseGame);
                 }
             }
+            Message::SetSafeNeighbours(safe) => {
+                self.options.safe_neighbours = safe;
+            }
+            Message::SetNoGuess(no_guess) => {
+                self.options.no_guess = no_guess;
+            }
+            Message::SetSeed(seed) => {
+                if seed.is_empty() {
+                    self.options.seed = None;
+                } else if let Ok(seed) = seed.parse() {
+                    self.options.seed = Some(seed);
+                }
+            }
+            Message::Hint => match self.game.analyse() {
+                Ok(analysis) => {
+                    let width = self.game.board.as_ref().map_or(1, |b| b.dim.0);
+                    self.hint = analysis
+                        .best_move()
+                        .map(|i| Coord::new(i % width, i / width));
+                    self.hints += self.hint.is_some() as usize;
+                    self.error = None;
+                }
+                Err(err) => self.show_error(&err),
+            },
+            Message::SetHeatMap(heat_map) => {
+                self.heat_map = heat_map;
+                self.update_analysis();
+            }
+            Message::Tick => {
+                return self.perform(Action::GetTime);
+            }
         }
         Command::none()
     }
 
-    fn view(&self) -> Element<Message> {
-        let top_bar = row![
-            text(format!("Status: {:?}", self.client.state)),
-            text(format!("Time: {}", self.speed)),
-        ]
-        .spacing(15);
+    fn subscription(&self) -> Subscription<Message> {
+        // Only poll the server while the clock is running
+        if self.client.is_some() && self.game.state == client::State::Playing {
+            time::every(Duration::from_secs(1)).map(|_| Message::Tick)
+        } else {
+            Subscription::none()
+        }
+    }
+
+    fn view(&self) -> Element<'_, Message> {
+        let mut top_bar = Row::new().spacing(15);
+        match self.client {
+            Some(_) => {
+                top_bar = top_bar
+                    .push(text(&self.server_addr))
+                    .push(button("Disconnect").on_press(Message::Disconnect))
+                    .push(text(format!("Status: {:?}", self.game.state)))
+                    .push(text(format!("Time: {}", self.game.time)))
+                    .push(text(format!("Mines: {}", self.game.mines_remaining)))
+                    .push(button("Hint").on_press(Message::Hint))
+                    .push(text(
+                        self.game
+                            .seed
+                            .map(|s| format!("Seed: {}", s))
+                            .unwrap_or_default(),
+                    ));
+            }
+            None => {
+                top_bar = top_bar
+                    .push(text("Server"))
+                    .push(
+                        text_input("host:port", &self.server_addr)
+                            .on_input(Message::SetServerAddr)
+                            .on_submit(Message::Connect)
+                            .width(200),
+                    )
+                    .push(button("Connect").on_press(Message::Connect));
+            }
+        }
+        top_bar = top_bar.push(text(self.error.clone().unwrap_or_default()));
         let bottom_bar = row![
             text("W/H"),
             text_input("8", &self.dim.0.to_string())
@@ -116,12 +425,30 @@ impl Application for MinesweeperGUI {
             text_input("10", &self.mine_count.to_string())
                 .on_input(|v| { Message::SetMineCount(v.parse().ok()) })
                 .width(100),
+            checkbox("Safe Neighbours", self.options.safe_neighbours)
+                .on_toggle(Message::SetSafeNeighbours),
+            checkbox("No Guess", self.options.no_guess).on_toggle(Message::SetNoGuess),
+            checkbox("Heat Map", self.heat_map).on_toggle(Message::SetHeatMap),
+            text("Seed"),
+            text_input(
+                "Random",
+                &self.options.seed.map(|s| s.to_string()).unwrap_or_default()
+            )
+            .on_input(Message::SetSeed)
+            .width(200),
+            text(format!(
+                "Won {}/{}, hinted {} ({} won)",
+                self.stats.won, self.stats.played, self.stats.hinted, self.stats.hinted_won
+            )),
         ]
         .padding(15);
         let mut row = Row::new();
 
-        if self.client.state.should_display() {
-            let (width, height) = self.client.board.clone().unwrap().dim.clone();
+        let board = Some(&self.game)
+            .filter(|g| self.client.is_some() && g.state.should_display())
+            .and_then(|g| g.board.as_ref());
+        if let Some(board) = board {
+            let (width, height) = board.dim;
 
             let max_width = 1200u16;
             let max_height = 800u16;
@@ -131,31 +458,74 @@ impl Application for MinesweeperGUI {
             for x in 0..width {
                 let mut column = Column::new();
                 for y in 0..height {
-                    let mut path_img = IMAGES[10];
-                    if let Some(ref board) = self.client.board {
-                        let cell = &board.cells[x + y * width];
-                        path_img = match cell {
-                            client::Cell::Revealed(val) => IMAGES[*val as usize],
-                            client::Cell::Hidden(state) => {
-                                if *state {
-                                    IMAGES[11]
-                                } else {
-                                    IMAGES[10]
-                                }
-                            }
-                            client::Cell::Mine => IMAGES[9],
-                            client::Cell::MineExploded => IMAGES[12],
-
-                        };
+                    // The hinted cell is drawn smaller, inside a green frame
+                    let hinted = self.hint == Some(Coord::new(x, y));
+                    let size = if hinted {
+                        b_size.saturating_sub(2 * HINT_FRAME)
+                    } else {
+                        b_size
+                    };
+                    let cell = board.cell_at(Coord::new(x, y));
+                    let probability = match cell {
+                        Some(client::Cell::Hidden | client::Cell::Question) => self
+                            .analysis
+                            .as_ref()
+                            .and_then(|a| a.probabilities[x + y * width]),
+                        _ => None,
+                    };
+                    let path_img = match cell {
+                        Some(client::Cell::Revealed(val)) => Some(IMAGES[*val as usize]),
+                        Some(client::Cell::Flagged) => Some(IMAGES[11]),
+                        // There is no image for question marks so they are drawn as text
+                        Some(client::Cell::Question) => None,
+                        Some(client::Cell::Mine) => Some(IMAGES[9]),
+                        Some(client::Cell::MineExploded) => Some(IMAGES[12]),
+                        Some(client::Cell::Hidden) | None => Some(IMAGES[10]),
+                    };
+                    let mut content: Element<'_, Message> = match (path_img, probability) {
+                        // Hidden cells become tiles tinted from green for safe to red for a mine
+                        (_, Some(p)) => {
+                            let label = match cell {
+                                Some(client::Cell::Question) => "?",
+                                _ => "",
+                            };
+                            container(text(label))
+                                .center_x()
+                                .center_y()
+                                .width(size)
+                                .height(size)
+                                .style(container::Appearance {
+                                    background: Some(heat_colour(p).into()),
+                                    border: Border::with_radius(2),
+                                    ..Default::default()
+                                })
+                                .into()
+                        }
+                        (Some(path_img), None) => Image::<image::Handle>::new(path_img)
+                            .width(size)
+                            .height(size)
+                            .into(),
+                        (None, None) => container(text("?"))
+                            .center_x()
+                            .center_y()
+                            .width(size)
+                            .height(size)
+                            .into(),
+                    };
+                    if hinted {
+                        content = container(content)
+                            .padding(HINT_FRAME)
+                            .style(container::Appearance {
+                                background: Some(Color::from_rgb8(0x2e, 0xb8, 0x4b).into()),
+                                ..Default::default()
+                            })
+                            .into();
                     }
                     column = column.push(
-                        mouse_area(
-                            Image::<image::Handle>::new(path_img)
-                                .width(b_size)
-                                .height(b_size),
-                        )
-                        .on_right_press(Message::FlagCell(x + y * width))
-                        .on_press(Message::RevealCell(x + y * width)),
+                        mouse_area(content)
+                            .on_right_press(Message::CycleMark(Coord::new(x, y)))
+                            .on_middle_press(Message::ChordCell(Coord::new(x, y)))
+                            .on_press(Message::RevealCell(Coord::new(x, y))),
                     );
                 }
                 row = row.push(column);
@@ -175,6 +545,16 @@ impl Application for MinesweeperGUI {
     }
 }
 
+/// Colour of a hidden cell on the heat map, green when safe through yellow to red for a mine
+fn heat_colour(probability: f64) -> Color {
+    let p = probability.clamp(0.0, 1.0) as f32;
+    Color::from_rgb(
+        0.2 + 0.8 * (2.0 * p).min(1.0),
+        0.8 * (2.0 - 2.0 * p).min(1.0),
+        0.2,
+    )
+}
+
 fn main() -> iced::Result {
-    MinesweeperGUI::run(Settings::default())
+    MinesweeperGUI::run(Settings::with_flags(Args::parse()))
 }
@@ -0,0 +1,180 @@
+//! Requests shared by both clients, built and answered without doing any I/O
+//!
+//! A client only has to exchange the message of a [`Request`] with the server and hand the
+//! reply back, so the blocking and the async client differ in nothing but their sockets.
+
+use crate::{Cell, Game, GameOptions, Result};
+use protocol::v3::{ClientMsg, ServerMsg};
+
+/// Applies the server's reply to a request to the game
+type Apply<T> = Box<dyn FnOnce(&mut Game, ServerMsg) -> Result<T> + Send>;
+
+/// A message for the server together with how its reply changes the game
+pub(crate) struct Request<T> {
+    pub msg: ClientMsg,
+    apply: Apply<T>,
+}
+impl<T> Request<T> {
+    fn new(
+        msg: ClientMsg,
+        apply: impl FnOnce(&mut Game, ServerMsg) -> Result<T> + Send + 'static,
+    ) -> Self {
+        Self {
+            msg,
+            apply: Box::new(apply),
+        }
+    }
+
+    /// Updates the game with the server's reply to this request
+    pub fn apply(self, game: &mut Game, reply: ServerMsg) -> Result<T> {
+        (self.apply)(game, reply)
+    }
+}
+impl Request<()> {
+    /// Starts a new game, replacing the current one
+    pub fn new_game(dim: (usize, usize), mine_count: usize, options: GameOptions) -> Request<()> {
+        // Saturating so oversized requests are rejected by the server
+        let msg = ClientMsg::NewGame(
+            u32::try_from(dim.0).unwrap_or(u32::MAX),
+            u32::try_from(dim.1).unwrap_or(u32::MAX),
+            u32::try_from(mine_count).unwrap_or(u32::MAX),
+            options,
+        );
+        Request::new(msg, move |game, reply| {
+            game.apply_new_game(dim, mine_count, reply)
+        })
+    }
+
+    /// Ends the current game
+    pub fn close_game() -> Request<()> {
+        Request::new(ClientMsg::CloseGame(), Game::apply_close_game)
+    }
+
+    /// Reveals a cell, failing without asking the server if no game is being played
+    pub fn reveal(game: &Game, index: usize) -> Result<Request<()>> {
+        Self::make_move(game, ClientMsg::Reveal(wire_index(index)))
+    }
+
+    /// Chords a revealed number, failing without asking the server if no game is being played
+    pub fn chord(game: &Game, index: usize) -> Result<Request<()>> {
+        Self::make_move(game, ClientMsg::Chord(wire_index(index)))
+    }
+
+    /// Marks a cell as `cell`, which is shown once the server accepted the mark
+    pub fn mark(game: &Game, index: usize, cell: Cell) -> Result<Request<()>> {
+        game.playing_board()?;
+        let msg = match cell {
+            Cell::Flagged => ClientMsg::Flag(wire_index(index)),
+            Cell::Question => ClientMsg::Mark(wire_index(index)),
+            _ => ClientMsg::Unflag(wire_index(index)),
+        };
+        Ok(Request::new(msg, move |game, reply| {
+            game.apply_mark(index, cell, reply)
+        }))
+    }
+
+    /// A move which applies the cells revealed by the server
+    fn make_move(game: &Game, msg: ClientMsg) -> Result<Request<()>> {
+        game.playing_board()?;
+        Ok(Request::new(msg, Game::apply_move))
+    }
+}
+impl Request<String> {
+    /// Asks how long the current game has been running
+    pub fn get_time() -> Request<String> {
+        Request::new(ClientMsg::GetTime(), Game::apply_time)
+    }
+}
+
+/// Converts an index for the wire, saturating so the server rejects it instead of it wrapping
+fn wire_index(index: usize) -> u32 {
+    u32::try_from(index).unwrap_or(u32::MAX)
+}
+
+/// Defines the game methods of a client on top of its `send` method
+///
+/// Invoked with `async; .await` for the async client and with nothing for the blocking one.
+macro_rules! game_methods {
+    ($($async:ident)?; $($await:tt)*) => {
+        /// Starts a new game, replacing the current one
+        pub $($async)? fn new_game(
+            &mut self,
+            dim: (usize, usize),
+            mine_count: usize,
+            options: $crate::GameOptions,
+        ) -> $crate::Result<()> {
+            self.send($crate::request::Request::new_game(dim, mine_count, options))$($await)*
+        }
+
+        /// Ends the current game
+        pub $($async)? fn close_game(&mut self) -> $crate::Result<()> {
+            self.send($crate::request::Request::close_game())$($await)*
+        }
+
+        /// Asks the server how long the current game has been running
+        pub $($async)? fn get_time(&mut self) -> $crate::Result<String> {
+            self.send($crate::request::Request::get_time())$($await)*
+        }
+
+        /// Reveals a cell
+        pub $($async)? fn reveal_cell(&mut self, index: usize) -> $crate::Result<()> {
+            let request = $crate::request::Request::reveal(&self.game, index)?;
+            self.send(request)$($await)*
+        }
+
+        /// Reveals the unflagged neighbours of a revealed number once as many of them are flagged
+        pub $($async)? fn chord_cell(&mut self, index: usize) -> $crate::Result<()> {
+            let request = $crate::request::Request::chord(&self.game, index)?;
+            self.send(request)$($await)*
+        }
+
+        /// Flags a hidden cell as a mine
+        pub $($async)? fn flag_cell(&mut self, index: usize) -> $crate::Result<()> {
+            let request = $crate::request::Request::mark(&self.game, index, $crate::Cell::Flagged)?;
+            self.send(request)$($await)*
+        }
+
+        /// Removes a flag or question mark from a cell
+        pub $($async)? fn unflag_cell(&mut self, index: usize) -> $crate::Result<()> {
+            let request = $crate::request::Request::mark(&self.game, index, $crate::Cell::Hidden)?;
+            self.send(request)$($await)*
+        }
+
+        /// Marks a hidden cell with a question mark
+        pub $($async)? fn question_cell(&mut self, index: usize) -> $crate::Result<()> {
+            let request = $crate::request::Request::mark(&self.game, index, $crate::Cell::Question)?;
+            self.send(request)$($await)*
+        }
+
+        /// Reveals the cell at a coordinate
+        pub $($async)? fn reveal_at(&mut self, x: usize, y: usize) -> $crate::Result<()> {
+            let index = self.game.index_at(x, y)?;
+            self.reveal_cell(index)$($await)*
+        }
+
+        /// Chords the cell at a coordinate
+        pub $($async)? fn chord_at(&mut self, x: usize, y: usize) -> $crate::Result<()> {
+            let index = self.game.index_at(x, y)?;
+            self.chord_cell(index)$($await)*
+        }
+
+        /// Flags the cell at a coordinate
+        pub $($async)? fn flag_at(&mut self, x: usize, y: usize) -> $crate::Result<()> {
+            let index = self.game.index_at(x, y)?;
+            self.flag_cell(index)$($await)*
+        }
+
+        /// Removes a flag or question mark from the cell at a coordinate
+        pub $($async)? fn unflag_at(&mut self, x: usize, y: usize) -> $crate::Result<()> {
+            let index = self.game.index_at(x, y)?;
+            self.unflag_cell(index)$($await)*
+        }
+
+        /// Marks the cell at a coordinate with a question mark
+        pub $($async)? fn question_at(&mut self, x: usize, y: usize) -> $crate::Result<()> {
+            let index = self.game.index_at(x, y)?;
+            self.question_cell(index)$($await)*
+        }
+    };
+}
+pub(crate) use game_methods;
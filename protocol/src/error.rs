@@ -0,0 +1,155 @@
+//! Catalogue of the error codes carried by Error messages
+//!
+//! Senders Fault: 0..=99, Recipients Fault: 100..=199, Ok: 200..=299
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Error code sent in place of a reply when a message could not be handled
+///
+/// Codes missing from the catalogue decode as Unknown so peers can add codes without
+/// breaking older ones.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(into = "u16", from = "u16")]
+pub enum ErrorCode {
+    Unrecoverable,
+    UnsupportedVersion,
+    MalformedFrame,
+    IndexOutOfRange,
+    BoardTooLarge,
+    TooManyMines,
+    NoGameInProgress,
+    GameOver,
+    BoardTooSmall,
+    TooFewMines,
+    IdleTimeout,
+    CellFlagged,
+    CellRevealed,
+    SessionExpired,
+    SessionTakenOver,
+
+    RecipientUnrecoverable,
+    GenerationFailed,
+    ServerFull,
+
+    Success,
+
+    /// A code this side does not know, sent by a newer peer
+    Unknown(u16),
+}
+impl ErrorCode {
+    /// Numeric code sent over the wire
+    pub fn code(self) -> u16 {
+        match self {
+            ErrorCode::Unrecoverable => 0,
+            ErrorCode::UnsupportedVersion => 1,
+            ErrorCode::MalformedFrame => 2,
+            ErrorCode::IndexOutOfRange => 3,
+            ErrorCode::BoardTooLarge => 4,
+            ErrorCode::TooManyMines => 5,
+            ErrorCode::NoGameInProgress => 6,
+            ErrorCode::GameOver => 7,
+            ErrorCode::BoardTooSmall => 8,
+            ErrorCode::TooFewMines => 9,
+            ErrorCode::IdleTimeout => 10,
+            ErrorCode::CellFlagged => 11,
+            ErrorCode::CellRevealed => 12,
+            ErrorCode::SessionExpired => 13,
+            ErrorCode::SessionTakenOver => 14,
+            ErrorCode::RecipientUnrecoverable => 100,
+            ErrorCode::GenerationFailed => 101,
+            ErrorCode::ServerFull => 102,
+            ErrorCode::Success => 200,
+            ErrorCode::Unknown(code) => code,
+        }
+    }
+
+    /// Returns true if the error was caused by whoever sent the failing message
+    pub fn is_senders_fault(self) -> bool {
+        self.code() <= 99
+    }
+
+    /// Human readable description of the error
+    pub fn message(self) -> &'static str {
+        match self {
+            ErrorCode::Unrecoverable => "Unrecoverable error",
+            ErrorCode::UnsupportedVersion => "Protocol version is not supported",
+            ErrorCode::MalformedFrame => "Message could not be decoded",
+            ErrorCode::IndexOutOfRange => "Cell index is outside of the board",
+            ErrorCode::BoardTooLarge => "Board dimensions exceed the maximum",
+            ErrorCode::TooManyMines => "Mine count does not fit on the board",
+            ErrorCode::NoGameInProgress => "No game is in progress",
+            ErrorCode::GameOver => "Game is already over",
+            ErrorCode::BoardTooSmall => "Board dimensions are below the minimum",
+            ErrorCode::TooFewMines => "Mine count is below the minimum",
+            ErrorCode::IdleTimeout => "Connection was idle for too long",
+            ErrorCode::CellFlagged => "Cell is flagged and can not be revealed",
+            ErrorCode::CellRevealed => "Cell is already revealed and can not be marked",
+            ErrorCode::SessionExpired => "Session is unknown or its grace period ran out",
+            ErrorCode::SessionTakenOver => "Session was resumed on another connection",
+            ErrorCode::RecipientUnrecoverable => "Recipient hit an unrecoverable error",
+            ErrorCode::GenerationFailed => "No board matching the options was found in time",
+            ErrorCode::ServerFull => "Server is serving as many connections as it allows",
+            ErrorCode::Success => "Success",
+            ErrorCode::Unknown(_) => "Unknown error",
+        }
+    }
+}
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (error code {})", self.message(), self.code())
+    }
+}
+impl From<ErrorCode> for u16 {
+    fn from(value: ErrorCode) -> Self {
+        value.code()
+    }
+}
+impl From<u16> for ErrorCode {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => ErrorCode::Unrecoverable,
+            1 => ErrorCode::UnsupportedVersion,
+            2 => ErrorCode::MalformedFrame,
+            3 => ErrorCode::IndexOutOfRange,
+            4 => ErrorCode::BoardTooLarge,
+            5 => ErrorCode::TooManyMines,
+            6 => ErrorCode::NoGameInProgress,
+            7 => ErrorCode::GameOver,
+            8 => ErrorCode::BoardTooSmall,
+            9 => ErrorCode::TooFewMines,
+            10 => ErrorCode::IdleTimeout,
+            11 => ErrorCode::CellFlagged,
+            12 => ErrorCode::CellRevealed,
+            13 => ErrorCode::SessionExpired,
+            14 => ErrorCode::SessionTakenOver,
+            100 => ErrorCode::RecipientUnrecoverable,
+            101 => ErrorCode::GenerationFailed,
+            102 => ErrorCode::ServerFull,
+            200 => ErrorCode::Success,
+            code => ErrorCode::Unknown(code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_round_trip() {
+        for code in (0..=300).chain([u16::MAX]) {
+            assert_eq!(ErrorCode::from(code).code(), code);
+        }
+        assert_eq!(ErrorCode::from(101), ErrorCode::GenerationFailed);
+    }
+
+    #[test]
+    fn unknown_codes_decode() {
+        let bytes = bincode::serialize(&150u16).unwrap();
+        let code: ErrorCode = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(code, ErrorCode::Unknown(150));
+        assert!(!code.is_senders_fault());
+        assert_eq!(bincode::serialize(&code).unwrap(), bytes);
+    }
+}
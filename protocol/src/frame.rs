@@ -0,0 +1,142 @@
+//! Length-prefixed framing so every message is read completely and exactly once
+//!
+//! length (u32, big endian) | opcode (u8) | payload (length bytes)
+
+use std::io::{self, Read};
+
+/// Number of bytes preceding the payload of a frame
+pub const HEADER_LEN: usize = 5;
+
+/// Header read from the front of every frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameHeader {
+    pub opcode: u8,
+    pub len: usize,
+}
+impl FrameHeader {
+    /// Parses a header, rejecting payloads larger than `max_len`
+    pub fn parse(bytes: &[u8; HEADER_LEN], max_len: usize) -> io::Result<Self> {
+        let len = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        if len > max_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Frame of {} bytes exceeds the limit of {} bytes",
+                    len, max_len
+                ),
+            ));
+        }
+        Ok(Self {
+            opcode: bytes[4],
+            len,
+        })
+    }
+}
+
+/// Wraps a payload into a frame
+pub fn encode(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.push(opcode);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Reads a single frame, returns None if the stream closed before a new frame started
+pub fn read_frame<R: Read>(
+    reader: &mut R,
+    max_len: usize,
+) -> io::Result<Option<(FrameHeader, Vec<u8>)>> {
+    let mut header = [0u8; HEADER_LEN];
+    match reader.read_exact(&mut header) {
+        Ok(()) => (),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let header = FrameHeader::parse(&header, max_len)?;
+
+    let mut payload = vec![0u8; header.len];
+    reader.read_exact(&mut payload)?;
+    Ok(Some((header, payload)))
+}
+
+/// Reads a single frame from an async stream, returns None if the stream closed before a new frame started
+#[cfg(feature = "tokio")]
+pub async fn read_frame_async<R: tokio::io::AsyncRead + Unpin>(
+    reader: &mut R,
+    max_len: usize,
+) -> io::Result<Option<(FrameHeader, Vec<u8>)>> {
+    use tokio::io::AsyncReadExt;
+
+    let mut header = [0u8; HEADER_LEN];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => (),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let header = FrameHeader::parse(&header, max_len)?;
+
+    let mut payload = vec![0u8; header.len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some((header, payload)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Hands out one byte per read, like a stream split up by TCP
+    struct Trickle<'a>(&'a [u8]);
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((&byte, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = byte;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn reads_back_to_back_frames() {
+        let mut stream = encode(3, b"hello");
+        stream.extend(encode(7, b""));
+        let mut reader = Cursor::new(stream);
+
+        let (header, payload) = read_frame(&mut reader, 16).unwrap().unwrap();
+        assert_eq!(header, FrameHeader { opcode: 3, len: 5 });
+        assert_eq!(payload, b"hello");
+        let (header, payload) = read_frame(&mut reader, 16).unwrap().unwrap();
+        assert_eq!(header, FrameHeader { opcode: 7, len: 0 });
+        assert!(payload.is_empty());
+        assert!(read_frame(&mut reader, 16).unwrap().is_none());
+    }
+
+    #[test]
+    fn reads_frames_split_into_pieces() {
+        let stream = encode(1, &[42; 100]);
+        let (header, payload) = read_frame(&mut Trickle(&stream), 100).unwrap().unwrap();
+        assert_eq!(header.opcode, 1);
+        assert_eq!(payload, vec![42; 100]);
+    }
+
+    #[test]
+    fn rejects_oversized_frames() {
+        let stream = encode(1, &[0; 17]);
+        let err = read_frame(&mut Cursor::new(stream), 16).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn fails_on_truncated_payloads() {
+        let mut stream = encode(1, b"hello");
+        stream.pop();
+        let err = read_frame(&mut Cursor::new(stream), 16).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,15 @@
+//! Defines the protocol shared by the MineSweeper client and server
+pub mod error;
+pub mod frame;
+pub mod message;
+pub mod reveals;
+pub mod v1;
+pub mod v2;
+pub mod v3;
+pub mod zip;
+
+pub use error::ErrorCode;
+pub use message::Message;
+
+/// Version every connection speaks until SetVersion selects another one
+pub const HANDSHAKE_VERSION: u16 = 2;
@@ -0,0 +1,90 @@
+//! Encoding shared by the messages of every protocol version
+
+use crate::frame::{self, FrameHeader};
+use crate::zip;
+use anyhow::{bail, Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+pub type Bytes = Vec<u8>;
+
+/// A message which can be sent over the wire as a frame
+pub trait Message: Serialize + DeserializeOwned {
+    /// Largest size a payload may decompress to, bounding what a small frame can expand into
+    const MAX_DECODED: usize = crate::v2::MAX_BYTES;
+
+    /// Opcode identifying the message in its frame header
+    fn opcode(&self) -> u8;
+
+    /// Serializes the message into a complete frame
+    fn to_frame(&self) -> Result<Bytes> {
+        Ok(frame::encode(self.opcode(), &self.to_bytes()?))
+    }
+    /// Deserializes a message from a frame's header and payload
+    fn from_frame(header: &FrameHeader, payload: &Bytes) -> Result<Self> {
+        let msg = Self::from_bytes(payload)?;
+        if msg.opcode() != header.opcode {
+            bail!(
+                "Frame opcode {} does not match message opcode {}",
+                header.opcode,
+                msg.opcode()
+            );
+        }
+        Ok(msg)
+    }
+    fn to_bytes(&self) -> Result<Bytes> {
+        let bytes = bincode::serialize(&self).context("Failed to serialize message")?;
+        Ok(zip::encode(&bytes)?)
+    }
+    fn from_bytes(bytes: &Bytes) -> Result<Self> {
+        bincode::deserialize(&zip::decode(bytes, Self::MAX_DECODED)?)
+            .context("Failed to deserialize message")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::{read_frame, HEADER_LEN};
+    use crate::v2::{ClientMsg, GameOptions, MAX_BYTES};
+
+    #[test]
+    fn frames_round_trip() {
+        let msg = ClientMsg::NewGame(30, 16, 99, GameOptions::default());
+        let bytes = msg.to_frame().unwrap();
+        let (header, payload) = read_frame(&mut bytes.as_slice(), MAX_BYTES)
+            .unwrap()
+            .unwrap();
+        assert_eq!(header.opcode, msg.opcode());
+        assert!(ClientMsg::from_frame(&header, &payload).unwrap() == msg);
+    }
+
+    #[test]
+    fn rejects_payloads_decompressing_past_the_limit() {
+        // Zeros compress to a tiny frame that would expand far beyond the limit
+        let header = FrameHeader {
+            len: 0,
+            opcode: ClientMsg::GetVersions().opcode(),
+        };
+        let bomb = zip::encode(&vec![0; ClientMsg::MAX_DECODED + 1]).unwrap();
+        assert!(bomb.len() < 1024);
+        let Err(err) = ClientMsg::from_frame(&header, &bomb) else {
+            panic!("Decompressed past the limit");
+        };
+        assert!(format!("{:#}", err).contains("decompresses to more than"));
+
+        // The limit itself is still allowed
+        let zeros = zip::encode(&[0; 100]).unwrap();
+        assert_eq!(zip::decode(&zeros, 100).unwrap().len(), 100);
+        assert!(zip::decode(&zeros, 99).is_err());
+    }
+
+    #[test]
+    fn rejects_mismatched_opcodes() {
+        let mut bytes = ClientMsg::Reveal(5).to_frame().unwrap();
+        bytes[HEADER_LEN - 1] = ClientMsg::Chord(5).opcode();
+        let (header, payload) = read_frame(&mut bytes.as_slice(), MAX_BYTES)
+            .unwrap()
+            .unwrap();
+        assert!(ClientMsg::from_frame(&header, &payload).is_err());
+    }
+}
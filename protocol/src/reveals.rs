@@ -0,0 +1,249 @@
+//! Compact encoding of the cells revealed by a single move
+//!
+//! Revealed indices are sorted and grouped into runs of consecutive cells.
+//! Each run is written as varint(gap since the end of the previous run) | varint(run length)
+//! followed by the values of its cells packed two per byte, low nibble first.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Cells revealed by a move as (index, value) pairs
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+#[serde(into = "Vec<u8>", try_from = "Vec<u8>")]
+pub struct Reveals(Vec<(usize, u8)>);
+impl Reveals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a revealed cell, values have to fit in a nibble
+    pub fn push(&mut self, index: usize, value: u8) {
+        assert!(
+            value <= 0x0F,
+            "Revealed value {} does not fit in a nibble",
+            value
+        );
+        self.0.push((index, value));
+    }
+
+    /// Iterates over the revealed (index, value) pairs
+    pub fn iter(&self) -> impl Iterator<Item = (usize, u8)> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Encodes the cells as runs of consecutive indices
+    pub fn encode(&self) -> Vec<u8> {
+        let mut cells = self.0.clone();
+        cells.sort_unstable_by_key(|(i, _)| *i);
+        cells.dedup_by_key(|(i, _)| *i);
+
+        let mut bytes = vec![];
+        let mut end = 0;
+        let mut start = 0;
+        while start < cells.len() {
+            let mut len = 1;
+            while start + len < cells.len() && cells[start + len].0 == cells[start].0 + len {
+                len += 1;
+            }
+            write_varint(&mut bytes, cells[start].0 - end);
+            write_varint(&mut bytes, len);
+            for pair in cells[start..start + len].chunks(2) {
+                let high = pair.get(1).map_or(0, |(_, v)| *v);
+                bytes.push(pair[0].1 | (high << 4));
+            }
+            end = cells[start].0 + len;
+            start += len;
+        }
+        bytes
+    }
+
+    /// Decodes cells written by encode, fails if the bytes are truncated or overflow
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, MalformedReveals> {
+        let mut cells = vec![];
+        let mut end: usize = 0;
+        while !bytes.is_empty() {
+            let gap = read_varint(&mut bytes)?;
+            let len = read_varint(&mut bytes)?;
+            let start = end.checked_add(gap).ok_or(MalformedReveals)?;
+            end = start.checked_add(len).ok_or(MalformedReveals)?;
+
+            let packed = len.div_ceil(2);
+            if packed > bytes.len() {
+                return Err(MalformedReveals);
+            }
+            let (values, rest) = bytes.split_at(packed);
+            for (offset, index) in (start..end).enumerate() {
+                let byte = values[offset / 2];
+                let value = if offset % 2 == 0 {
+                    byte & 0x0F
+                } else {
+                    byte >> 4
+                };
+                cells.push((index, value));
+            }
+            bytes = rest;
+        }
+        Ok(Self(cells))
+    }
+}
+impl FromIterator<(usize, u8)> for Reveals {
+    fn from_iter<T: IntoIterator<Item = (usize, u8)>>(iter: T) -> Self {
+        let mut reveals = Self::new();
+        for (index, value) in iter {
+            reveals.push(index, value);
+        }
+        reveals
+    }
+}
+impl From<Reveals> for Vec<u8> {
+    fn from(value: Reveals) -> Self {
+        value.encode()
+    }
+}
+impl TryFrom<Vec<u8>> for Reveals {
+    type Error = MalformedReveals;
+
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        Self::decode(&value)
+    }
+}
+
+/// Writes an unsigned LEB128 varint
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Reads an unsigned LEB128 varint from the front of `bytes`
+fn read_varint(bytes: &mut &[u8]) -> Result<usize, MalformedReveals> {
+    let mut value: usize = 0;
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = bytes.split_first().ok_or(MalformedReveals)?;
+        *bytes = rest;
+        let bits = (byte & 0x7F) as usize;
+        // Bits shifted past the top of a usize would be lost
+        if shift >= usize::BITS || (bits << shift) >> shift != bits {
+            return Err(MalformedReveals);
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+/// Returned when revealed cells could not be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MalformedReveals;
+impl fmt::Display for MalformedReveals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Revealed cells are truncated or out of range")
+    }
+}
+impl std::error::Error for MalformedReveals {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(reveals: &Reveals) -> Reveals {
+        Reveals::decode(&reveals.encode()).unwrap()
+    }
+
+    #[test]
+    fn sorts_and_drops_duplicates() {
+        let reveals: Reveals = [(5, 1), (2, 3), (5, 1), (3, 0), (9, 15)]
+            .into_iter()
+            .collect();
+        let decoded = round_trip(&reveals);
+        assert_eq!(
+            decoded.iter().collect::<Vec<_>>(),
+            vec![(2, 3), (3, 0), (5, 1), (9, 15)]
+        );
+    }
+
+    #[test]
+    fn empty() {
+        assert!(Reveals::new().encode().is_empty());
+        assert!(Reveals::decode(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn multi_byte_varints() {
+        let mut bytes = vec![];
+        write_varint(&mut bytes, 300);
+        assert_eq!(bytes, vec![0xAC, 0x02]);
+        write_varint(&mut bytes, usize::MAX);
+        let mut rest = bytes.as_slice();
+        assert_eq!(read_varint(&mut rest), Ok(300));
+        assert_eq!(read_varint(&mut rest), Ok(usize::MAX));
+        assert!(rest.is_empty());
+
+        // A far away cell followed by a run longer than fits in a single varint byte
+        let reveals: Reveals = std::iter::once((1_000_000, 4))
+            .chain((2_000_000..2_000_200).map(|i| (i, (i % 9) as u8)))
+            .collect();
+        assert_eq!(round_trip(&reveals), reveals);
+    }
+
+    #[test]
+    fn truncated() {
+        let cells: Vec<(usize, u8)> = (0..5).map(|i| (i * 200, 1)).collect();
+        let bytes = Reveals(cells.clone()).encode();
+        // Cutting between runs leaves the runs before the cut, anywhere else fails
+        let boundaries: Vec<usize> = (0..cells.len())
+            .map(|runs| Reveals(cells[..runs].to_vec()).encode().len())
+            .collect();
+        for len in 1..bytes.len() {
+            let decoded = Reveals::decode(&bytes[..len]);
+            match boundaries.iter().position(|&b| b == len) {
+                Some(runs) => assert_eq!(decoded, Ok(Reveals(cells[..runs].to_vec()))),
+                None => assert_eq!(decoded, Err(MalformedReveals)),
+            }
+        }
+        // A varint whose continuation bit promises another byte
+        assert_eq!(Reveals::decode(&[0x80]), Err(MalformedReveals));
+    }
+
+    #[test]
+    fn varint_overflow() {
+        let mut too_long = vec![0xFF; 10];
+        too_long.push(0x01);
+        assert_eq!(read_varint(&mut too_long.as_slice()), Err(MalformedReveals));
+
+        // Ten bytes fit a u64 only if the last one carries a single bit
+        let mut top_bits = vec![0xFF; 9];
+        top_bits.push(0x02);
+        assert_eq!(read_varint(&mut top_bits.as_slice()), Err(MalformedReveals));
+
+        // Runs ending past usize::MAX
+        let mut bytes = vec![];
+        write_varint(&mut bytes, usize::MAX);
+        write_varint(&mut bytes, 2);
+        bytes.push(0);
+        assert_eq!(Reveals::decode(&bytes), Err(MalformedReveals));
+    }
+
+    #[test]
+    fn len_beyond_the_remaining_bytes() {
+        // A run of 5 cells needs 3 bytes of values but only 1 follows
+        assert_eq!(Reveals::decode(&[0, 5, 0x12]), Err(MalformedReveals));
+        // A huge run with hardly any bytes must not be allocated
+        let mut bytes = vec![0];
+        write_varint(&mut bytes, usize::MAX / 2);
+        assert_eq!(Reveals::decode(&bytes), Err(MalformedReveals));
+    }
+}
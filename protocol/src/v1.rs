@@ -0,0 +1,53 @@
+//! Defines version 1 of the MineSweeper Client Server protocol
+
+use crate::message::Message;
+use serde::{Deserialize, Serialize};
+
+/// Message sent by client and received by server
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum ClientMsg {
+    // (message)
+    Error(String),
+    // ((width, height), mine_count)
+    Connect((usize, usize), usize),
+    // (index)
+    Reveal(usize),
+}
+impl Message for ClientMsg {
+    fn opcode(&self) -> u8 {
+        match self {
+            ClientMsg::Error(_) => 0,
+            ClientMsg::Connect(..) => 1,
+            ClientMsg::Reveal(_) => 2,
+        }
+    }
+}
+
+/// Message sent by server and received by client
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum ServerMsg {
+    // (message)
+    Error(String),
+    // ()
+    ConnectionAccepted,
+    // ([index, value])
+    RevealCells(Vec<(usize, u8)>),
+    // (time, [index, value])
+    GameWin(String, Vec<(usize, u8)>),
+    // (time, [index])
+    GameLoss(String, Vec<usize>),
+}
+impl Message for ServerMsg {
+    // Revealed cells take 9 bytes each so replies for a v2::MAX_DIM board outgrow v2::MAX_BYTES
+    const MAX_DECODED: usize = 1 << 17;
+
+    fn opcode(&self) -> u8 {
+        match self {
+            ServerMsg::Error(_) => 0,
+            ServerMsg::ConnectionAccepted => 1,
+            ServerMsg::RevealCells(_) => 2,
+            ServerMsg::GameWin(..) => 3,
+            ServerMsg::GameLoss(..) => 4,
+        }
+    }
+}
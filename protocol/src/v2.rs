@@ -0,0 +1,167 @@
+//! Defines version 2 of the MineSweeper Client Server protocol
+
+use crate::error::ErrorCode;
+use crate::message::Message;
+use serde::{Deserialize, Serialize};
+
+pub use crate::message::Bytes;
+pub use crate::reveals::Reveals;
+
+/// Largest frame payload either side will accept
+///
+/// Large enough for GameLoss listing every mine of a MAX_DIM x MAX_DIM board as a u16.
+pub const MAX_BYTES: usize = 32768;
+
+/// Smallest width or height NewGame accepts
+pub const MIN_DIM: usize = 1;
+/// Largest width or height NewGame accepts
+pub const MAX_DIM: usize = 100;
+/// Fewest mines NewGame accepts, at most width*height - 1 may be placed
+pub const MIN_MINES: usize = 1;
+
+/// Optional settings chosen per game with NewGame
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct GameOptions {
+    /// Keeps the 8 neighbours of the first revealed cell free of mines as well as the cell itself
+    pub safe_neighbours: bool,
+    /// Only generates boards which can be cleared by pure logic from the first revealed cell
+    pub no_guess: bool,
+    /// Makes mine placement reproducible, the server picks one when None
+    pub seed: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum ClientMsg {
+    // size: (u16)
+    // name: (error_code)
+    Error(ErrorCode),
+
+    // size: (u16)
+    // name: (version)
+    // If version is invalid then it throws an error.
+    SetVersion(u16),
+
+    // size: (u8, u8, u16, GameOptions)
+    // name: (width, height, mine_count, options)
+    // If width or height exceed MAX_DIM or are below MIN_DIM then throws an error.
+    // If mine_count exceeds width*height - 1 or is below MIN_MINES then throws an error.
+    NewGame(u8, u8, u16, GameOptions),
+
+    // size: (u16)
+    // name: (index)
+    // If index is out of range then throws an error.
+    Reveal(u16),
+
+    // size: ()
+    // name: ()
+    GetTime(),
+
+    // size: ()
+    // name: ()
+    CloseGame(),
+
+    // size: ()
+    // name: ()
+    GetVersions(),
+
+    // size: (u16)
+    // name: (index)
+    // Reveals the unflagged neighbours of a revealed number once as many of them are flagged.
+    // If index is out of range then throws an error.
+    Chord(u16),
+
+    // size: (u16)
+    // name: (index)
+    // Replied to with MinesRemaining, throws an error if the cell is revealed.
+    Flag(u16),
+
+    // size: (u16)
+    // name: (index)
+    // Clears a flag or question mark, replied to with MinesRemaining.
+    Unflag(u16),
+
+    // size: (u16)
+    // name: (index)
+    // Marks a cell with a question mark, replied to with MinesRemaining.
+    Mark(u16),
+}
+impl Message for ClientMsg {
+    fn opcode(&self) -> u8 {
+        match self {
+            ClientMsg::Error(_) => 0,
+            ClientMsg::SetVersion(_) => 1,
+            ClientMsg::NewGame(..) => 2,
+            ClientMsg::Reveal(_) => 3,
+            ClientMsg::GetTime() => 4,
+            ClientMsg::CloseGame() => 5,
+            ClientMsg::GetVersions() => 6,
+            ClientMsg::Chord(_) => 7,
+            ClientMsg::Flag(_) => 8,
+            ClientMsg::Unflag(_) => 9,
+            ClientMsg::Mark(_) => 10,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum ServerMsg {
+    // size: (u16)
+    // name: (error_code)
+    Error(ErrorCode),
+
+    // size: ()
+    // name: ()
+    Accepted(),
+
+    // size: (Reveals)
+    // name: ([(index, val)])
+    // Only the cells revealed by the move, see the reveals module for the encoding
+    RevealCells(Reveals),
+
+    // size: (String, Reveals)
+    // name: (time, [(index, val)])
+    GameWin(String, Reveals),
+
+    // size: (String, Vec<u16>)
+    // name: (time, Vec<index>)
+    GameLoss(String, Vec<u16>),
+
+    // size: (String)
+    // name: (time)
+    // Seconds since the first reveal, stops once the game is over
+    Time(String),
+
+    // size: (Vec<u16>)
+    // name: (Vec<version>)
+    Versions(Vec<u16>),
+
+    // size: (u64)
+    // name: (seed)
+    GameCreated(u64),
+
+    // size: (i32)
+    // name: (mine_count - flag_count)
+    // Negative once more cells are flagged than there are mines
+    MinesRemaining(i32),
+
+    // size: (u64)
+    // name: (token)
+    // Replaces Accepted as the reply to SetVersion(3), the token resumes the session later on
+    SessionAccepted(u64),
+}
+impl Message for ServerMsg {
+    fn opcode(&self) -> u8 {
+        match self {
+            ServerMsg::Error(_) => 0,
+            ServerMsg::Accepted() => 1,
+            ServerMsg::RevealCells(_) => 2,
+            ServerMsg::GameWin(..) => 3,
+            ServerMsg::GameLoss(..) => 4,
+            ServerMsg::Time(_) => 5,
+            ServerMsg::Versions(_) => 6,
+            ServerMsg::GameCreated(_) => 7,
+            ServerMsg::MinesRemaining(_) => 8,
+            ServerMsg::SessionAccepted(_) => 9,
+        }
+    }
+}
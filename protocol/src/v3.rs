@@ -0,0 +1,202 @@
+//! Defines version 3 of the MineSweeper Client Server protocol
+//!
+//! Identical to version 2 except that dimensions, mine counts and indices are u32,
+//! the largest board is chosen by the server up to MAX_DIM instead of being fixed by the protocol,
+//! and that sessions can be resumed on a new connection.
+
+use crate::error::ErrorCode;
+use crate::message::Message;
+use serde::{Deserialize, Serialize};
+
+pub use crate::message::Bytes;
+pub use crate::reveals::Reveals;
+pub use crate::v2::{GameOptions, MIN_DIM, MIN_MINES};
+
+/// Largest frame payload a client will accept, requests are still limited to v2::MAX_BYTES
+pub const MAX_BYTES: usize = 1 << 22;
+/// Largest width or height a server may accept
+///
+/// The largest reply is GameLoss listing every mine as a u32, which for a board of
+/// MAX_DIM x MAX_DIM still fits in MAX_BYTES.
+pub const MAX_DIM: usize = 1000;
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum ClientMsg {
+    // size: (u16)
+    // name: (error_code)
+    Error(ErrorCode),
+
+    // size: (u16)
+    // name: (version)
+    // If version is invalid then it throws an error.
+    SetVersion(u16),
+
+    // size: (u32, u32, u32, GameOptions)
+    // name: (width, height, mine_count, options)
+    // If width or height exceed the server's maximum, at most MAX_DIM, or are below MIN_DIM then throws an error.
+    // If mine_count exceeds width*height - 1 or is below MIN_MINES then throws an error.
+    NewGame(u32, u32, u32, GameOptions),
+
+    // size: (u32)
+    // name: (index)
+    // If index is out of range then throws an error.
+    Reveal(u32),
+
+    // size: ()
+    // name: ()
+    GetTime(),
+
+    // size: ()
+    // name: ()
+    CloseGame(),
+
+    // size: ()
+    // name: ()
+    GetVersions(),
+
+    // size: (u32)
+    // name: (index)
+    // Reveals the unflagged neighbours of a revealed number once as many of them are flagged.
+    // If index is out of range then throws an error.
+    Chord(u32),
+
+    // size: (u32)
+    // name: (index)
+    // Replied to with MinesRemaining, throws an error if the cell is revealed.
+    Flag(u32),
+
+    // size: (u32)
+    // name: (index)
+    // Clears a flag or question mark, replied to with MinesRemaining.
+    Unflag(u32),
+
+    // size: (u32)
+    // name: (index)
+    // Marks a cell with a question mark, replied to with MinesRemaining.
+    Mark(u32),
+
+    // size: (u64)
+    // name: (token)
+    // Takes over the game of an earlier session, replied to with Resumed.
+    // If the session is unknown or its grace period ran out then throws an error.
+    Resume(u64),
+}
+impl Message for ClientMsg {
+    fn opcode(&self) -> u8 {
+        match self {
+            ClientMsg::Error(_) => 0,
+            ClientMsg::SetVersion(_) => 1,
+            ClientMsg::NewGame(..) => 2,
+            ClientMsg::Reveal(_) => 3,
+            ClientMsg::GetTime() => 4,
+            ClientMsg::CloseGame() => 5,
+            ClientMsg::GetVersions() => 6,
+            ClientMsg::Chord(_) => 7,
+            ClientMsg::Flag(_) => 8,
+            ClientMsg::Unflag(_) => 9,
+            ClientMsg::Mark(_) => 10,
+            ClientMsg::Resume(_) => 11,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum ServerMsg {
+    // size: (u16)
+    // name: (error_code)
+    Error(ErrorCode),
+
+    // size: ()
+    // name: ()
+    Accepted(),
+
+    // size: (Reveals)
+    // name: ([(index, val)])
+    // Only the cells revealed by the move, see the reveals module for the encoding
+    RevealCells(Reveals),
+
+    // size: (String, Reveals)
+    // name: (time, [(index, val)])
+    GameWin(String, Reveals),
+
+    // size: (String, Vec<u32>)
+    // name: (time, Vec<index>)
+    GameLoss(String, Vec<u32>),
+
+    // size: (String)
+    // name: (time)
+    // Seconds since the first reveal, stops once the game is over
+    Time(String),
+
+    // size: (Vec<u16>)
+    // name: (Vec<version>)
+    Versions(Vec<u16>),
+
+    // size: (u64)
+    // name: (seed)
+    GameCreated(u64),
+
+    // size: (i32)
+    // name: (mine_count - flag_count)
+    // Negative once more cells are flagged than there are mines
+    MinesRemaining(i32),
+
+    // size: (u64)
+    // name: (token)
+    // Replaces Accepted as the reply to SetVersion(3), the token resumes the session later on
+    SessionAccepted(u64),
+
+    // size: (Option<BoardState>)
+    // name: (board)
+    // Reply to Resume, None if the session had no game
+    Resumed(Option<BoardState>),
+}
+impl Message for ServerMsg {
+    const MAX_DECODED: usize = MAX_BYTES;
+
+    fn opcode(&self) -> u8 {
+        match self {
+            ServerMsg::Error(_) => 0,
+            ServerMsg::Accepted() => 1,
+            ServerMsg::RevealCells(_) => 2,
+            ServerMsg::GameWin(..) => 3,
+            ServerMsg::GameLoss(..) => 4,
+            ServerMsg::Time(_) => 5,
+            ServerMsg::Versions(_) => 6,
+            ServerMsg::GameCreated(_) => 7,
+            ServerMsg::MinesRemaining(_) => 8,
+            ServerMsg::SessionAccepted(_) => 9,
+            ServerMsg::Resumed(_) => 10,
+        }
+    }
+}
+
+/// Value of a hidden cell in BoardState
+pub const CELL_HIDDEN: u8 = 9;
+/// Value of a flagged cell in BoardState
+pub const CELL_FLAGGED: u8 = 10;
+/// Value of a cell marked with a question mark in BoardState
+pub const CELL_QUESTION: u8 = 11;
+/// Value of a mine in BoardState, mines are only shown once the game is over
+pub const CELL_MINE: u8 = 12;
+
+/// Progress of a resumed game
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum GameStatus {
+    Playing,
+    Won,
+    Lost,
+}
+
+/// Everything the client needs to show a resumed game
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct BoardState {
+    pub width: u32,
+    pub height: u32,
+    /// One value per cell, 0..=8 for revealed numbers or one of the CELL_* constants
+    pub cells: Vec<u8>,
+    pub status: GameStatus,
+    pub time: String,
+    pub seed: u64,
+    pub mines_remaining: i32,
+}
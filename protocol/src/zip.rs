@@ -0,0 +1,27 @@
+//! Module aimed at compression of bytes to shrink network payload
+
+use flate2::bufread::{ZlibDecoder, ZlibEncoder};
+use flate2::Compression;
+use std::io::{self, prelude::*};
+
+/// Decompresses a byte array, failing if it decompresses to more than `limit` bytes
+pub fn decode(bytes: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    let mut e = ZlibDecoder::new(bytes).take(limit as u64 + 1);
+    let mut decompressed_bytes = Vec::new();
+    e.read_to_end(&mut decompressed_bytes)?;
+    if decompressed_bytes.len() > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Payload decompresses to more than {} bytes", limit),
+        ));
+    }
+    Ok(decompressed_bytes)
+}
+
+/// Compresses a byte array
+pub fn encode(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut e = ZlibEncoder::new(bytes, Compression::best());
+    let mut compressed_bytes = Vec::new();
+    e.read_to_end(&mut compressed_bytes)?;
+    Ok(compressed_bytes)
+}
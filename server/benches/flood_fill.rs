@@ -0,0 +1,29 @@
+//! Measures revealing a sparse board, where a single click opens up most of it
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use protocol::v3::GameOptions;
+use server::board::BoardInstance;
+
+/// Square boards with roughly one mine per hundred cells, the last is the largest a server allows
+const SIZES: [usize; 3] = [30, 100, 1000];
+
+fn flood_fill(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flood_fill");
+    for size in SIZES {
+        let first = size / 2 * size + size / 2;
+        let mut board =
+            BoardInstance::init(&(size, size), size * size / 100, &GameOptions::default(), 0);
+        board.place_mines(first);
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), &board, |b, board| {
+            b.iter_batched(
+                || board.clone(),
+                |mut board| board.reveal(first),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, flood_fill);
+criterion_main!(benches);
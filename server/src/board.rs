@@ -1,128 +1,284 @@
-use std::vec;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
+use protocol::v3::GameOptions;
 use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use solver::Tile;
+
+/// Most work spent looking for a board that can be solved without guessing
+///
+/// Counted in the solver's steps rather than in time, so a seed given by the client leads to
+/// the same board on every machine.
+const GENERATION_WORK: usize = 1 << 27;
+/// Longest time spent looking for a board that can be solved without guessing
+///
+/// Only applies when the server picked the seed, as it would make seeded boards differ
+/// between machines.
+const GENERATION_TIME_BUDGET: Duration = Duration::from_secs(2);
+/// Most cells a no-guess board may have, larger ones can not be generated within the budget
+pub const MAX_NO_GUESS_CELLS: usize = 300 * 300;
+
+/// What is left of the effort no-guess generation may spend
+struct Budget {
+    work: usize,
+    deadline: Option<Instant>,
+}
+impl Budget {
+    fn left(&self) -> bool {
+        self.work > 0
+            && self
+                .deadline
+                .is_none_or(|deadline| Instant::now() < deadline)
+    }
+
+    fn spend(&mut self, work: usize) {
+        self.work = self.work.saturating_sub(work);
+    }
+}
+
+/// What the player can see of a cell
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CellState {
+    Hidden,
+    Flagged,
+    Question,
+    Revealed,
+}
 
 #[derive(Clone, Debug)]
 pub struct Cell {
     // 0 -> 8 means that a cell is nearby a bomb; 255 means it is a bomb
     pub proximity: u8,
-    pub hidden: bool,
+    pub state: CellState,
+}
+impl Cell {
+    /// Returns true if revealing may open the cell, flags protect a cell until they are removed
+    pub fn can_reveal(&self) -> bool {
+        matches!(self.state, CellState::Hidden | CellState::Question)
+    }
 }
 
+#[derive(Clone)]
 pub struct BoardInstance {
     pub dim: (usize, usize),
     pub cells: Vec<Cell>,
     pub revealed_count: usize,
     pub mine_count: usize,
+    pub options: GameOptions,
+    // Mine placement is fully determined by the seed and the first revealed cell
+    pub seed: u64,
+    pub mines_placed: bool,
+    // Set by the first reveal and once the game is won or lost
+    pub started: Option<Instant>,
+    pub finished: Option<Instant>,
 }
 
 impl BoardInstance {
-    /// Creates a new board
-    pub fn init(dim: &(usize, usize), mine_count: usize) -> Self {
-        let mut cells: Vec<Cell> = vec![
+    /// Creates a new board, mines are placed by the first reveal so it can never hit one
+    pub fn init(dim: &(usize, usize), mine_count: usize, options: &GameOptions, seed: u64) -> Self {
+        let cells: Vec<Cell> = vec![
             Cell {
                 proximity: 0,
-                hidden: true
+                state: CellState::Hidden
             };
             dim.0 * dim.1
         ];
+        Self {
+            dim: *dim,
+            cells,
+            revealed_count: 0,
+            mine_count,
+            options: *options,
+            seed,
+            mines_placed: false,
+            started: None,
+            finished: None,
+        }
+    }
 
-        // Place mines randomly
-        let mut rng = rand::thread_rng();
-        let mut mine_indices: Vec<usize> = (0..cells.len()).collect();
-        mine_indices.shuffle(&mut rng);
-        for i in 0..mine_count {
-            cells[mine_indices[i]].proximity = u8::MAX; // 255 signifies a bomb
+    /// Places mines for the first reveal at `first`, returns false if the options could not be met in budget
+    ///
+    /// No-guess generation can take up to a few seconds, callers on an async runtime should
+    /// run it on a blocking thread.
+    pub fn place_mines(&mut self, first: usize) -> bool {
+        let mut safe = vec![first];
+        // Guessing can only be avoided if the first reveal opens up an area
+        if self.options.safe_neighbours || self.options.no_guess {
+            safe.extend(self.neighbours(first));
+        }
+        // Dense boards fall back to only keeping the revealed cell clear
+        if self.cells.len() - safe.len() < self.mine_count {
+            safe.truncate(1);
         }
 
-        // Update proximity counts for non-mine cells
-        for i in 0..cells.len() {
-            if cells[i].proximity != u8::MAX {
-                let (x, y) = ((i % dim.0), (i / dim.0));
-
-                let mut bombs_nearby: u8 = 0;
-                for dx in -1..=1 {
-                    for dy in -1..=1 {
-                        if dx == 0 && dy == 0 {
-                            continue;
-                        }
-                        let nx = x as isize + dx;
-                        let ny = y as isize + dy;
-                        if nx >= 0 && ny >= 0 && (nx as usize) < dim.0 && (ny as usize) < dim.1 {
-                            let neighbor_index = (nx as usize) + (ny as usize) * dim.0;
-                            if cells[neighbor_index].proximity == u8::MAX {
-                                bombs_nearby += 1;
-                            }
-                        }
-                    }
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        if !self.options.no_guess {
+            self.lay_mines(&mut rng, &safe);
+            return true;
+        }
+
+        let mut budget = Budget {
+            work: GENERATION_WORK,
+            deadline: match self.options.seed {
+                Some(_) => None,
+                None => Some(Instant::now() + GENERATION_TIME_BUDGET),
+            },
+        };
+        while budget.left() {
+            self.lay_mines(&mut rng, &safe);
+            if self.solvable(first, &mut budget) {
+                return true;
+            }
+        }
+        self.mines_placed = false;
+        false
+    }
+
+    /// Returns true if every safe cell can be revealed without guessing after revealing `first`
+    ///
+    /// Plays the board the way a player would see it, flagging every cell the solver proves
+    /// to be a mine and revealing every cell it proves to be safe until it runs out of either.
+    /// Gives up as soon as the budget runs out.
+    fn solvable(&self, first: usize, budget: &mut Budget) -> bool {
+        let mut tiles = vec![Tile::Hidden; self.cells.len()];
+        let mut revealed = 0;
+        let mut flags = 0;
+        let mut safe = vec![first];
+        loop {
+            while let Some(i) = safe.pop() {
+                if tiles[i] != Tile::Hidden {
+                    continue;
                 }
-                cells[i].proximity = bombs_nearby;
+                tiles[i] = Tile::Revealed(self.cells[i].proximity);
+                revealed += 1;
+                if self.cells[i].proximity == 0 {
+                    safe.extend(self.neighbours(i));
+                }
+            }
+            if revealed == self.cells.len() - self.mine_count {
+                return true;
+            }
+            if !budget.left() {
+                return false;
             }
+            let Ok(analysis) = solver::analyse(&tiles, self.dim.0, self.mine_count - flags) else {
+                return false;
+            };
+            budget.spend(analysis.effort);
+            if analysis.safe.is_empty() {
+                return false;
+            }
+            for i in analysis.mines {
+                tiles[i] = Tile::Flagged;
+                flags += 1;
+            }
+            safe = analysis.safe;
         }
-        Self {
-            dim: *dim,
-            cells,
-            revealed_count: 0,
-            mine_count,
+    }
+
+    /// Shuffles mines onto every cell outside of `safe` and updates the proximity counts
+    fn lay_mines<R: Rng>(&mut self, rng: &mut R, safe: &[usize]) {
+        for cell in self.cells.iter_mut() {
+            cell.proximity = 0;
+        }
+
+        let mut mine_indices: Vec<usize> = (0..self.cells.len())
+            .filter(|i| !safe.contains(i))
+            .collect();
+        mine_indices.shuffle(rng);
+        for i in mine_indices.into_iter().take(self.mine_count) {
+            self.cells[i].proximity = u8::MAX; // 255 signifies a bomb
+        }
+
+        // Update proximity counts for non-mine cells
+        for i in 0..self.cells.len() {
+            if self.cells[i].proximity != u8::MAX {
+                let bombs_nearby = self
+                    .neighbours(i)
+                    .into_iter()
+                    .filter(|n| self.cells[*n].proximity == u8::MAX)
+                    .count();
+                self.cells[i].proximity = bombs_nearby as u8;
+            }
         }
+        self.mines_placed = true;
     }
 
-    pub fn reveal_cells(&mut self, index: usize) -> Vec<u8> {
-        let mut res = vec![9u8; self.cells.len()];
+    /// Reveals a cell and returns the newly revealed cells, None if it was a mine
+    pub fn reveal_cells(&mut self, index: usize) -> Option<Vec<(usize, u8)>> {
+        self.started.get_or_insert_with(Instant::now);
 
         let revealed = self.reveal(index);
-        if revealed.len() == 0 && self.cells[index].hidden {
-            return vec![];
+        if revealed.is_empty() && self.cells[index].can_reveal() {
+            return None;
         }
-        for (i, v) in revealed {
-            res[i] = v;
+        Some(revealed)
+    }
+
+    /// Reveals the unflagged neighbours of a number once all of its mines are flagged
+    ///
+    /// Returns the cells like reveal_cells, None if a wrongly placed flag let a mine be revealed
+    pub fn chord_cells(&mut self, index: usize) -> Option<Vec<(usize, u8)>> {
+        let mut res = vec![];
+
+        let neighbours = self.neighbours(index);
+        let flags = neighbours
+            .iter()
+            .filter(|n| self.cells[**n].state == CellState::Flagged)
+            .count();
+        if self.cells[index].state != CellState::Revealed
+            || flags != self.cells[index].proximity as usize
+        {
+            return Some(res);
+        }
+
+        for n in neighbours {
+            if !self.cells[n].can_reveal() {
+                continue;
+            }
+            if self.cells[n].proximity == u8::MAX {
+                return None;
+            }
+            res.extend(self.reveal(n));
         }
-        res
-        
+        Some(res)
     }
-    /// Reveals cells and returns what has been revealed
+
+    /// Reveals a cell, opening the area around it breadth first when it has no neighbouring mines
+    ///
+    /// Returns every newly revealed cell once, empty if the cell is a mine or can not be revealed
     pub fn reveal(&mut self, index: usize) -> Vec<(usize, u8)> {
         let mut result = vec![];
 
-        // Ensure the index is valid and the cell is hidden
-        if index >= self.cells.len() || !self.cells[index].hidden {
+        // Ensure the index is valid and the cell is hidden and not flagged
+        if index >= self.cells.len() || !self.cells[index].can_reveal() {
             return result;
         }
 
         if self.cells[index].proximity == u8::MAX {
-            return vec![];
-        }
-
-        // Reveal the current cell
-        self.cells[index].hidden = false;
-        result.push((index, self.cells[index].proximity));
-        self.revealed_count += 1;
-
-        // If it's not a proximity of 0, stop recursion here
-        if self.cells[index].proximity > 0 {
             return result;
         }
 
-        // For cells with proximity 0, recursively reveal neighbors
-        let (x, y) = self.coord_from_index(index);
-        let neighbors = [
-            (x.saturating_sub(1), y),                   // Left
-            (x + 1, y),                                 // Right
-            (x, y.saturating_sub(1)),                   // Up
-            (x, y + 1),                                 // Down
-            (x.saturating_sub(1), y.saturating_sub(1)), // Upper-left
-            (x + 1, y + 1),                             // Lower-right
-            (x.saturating_sub(1), y + 1),               // Lower-left
-            (x + 1, y.saturating_sub(1)),               // Upper-right
-        ];
+        // One bit per cell, set once a cell has been queued so it is never queued twice
+        let mut visited = vec![0u64; self.cells.len().div_ceil(64)];
+        let mut queue = VecDeque::from([index]);
+        visited[index / 64] |= 1 << (index % 64);
 
-        for (nx, ny) in neighbors.iter() {
-            if *nx < self.dim.0 && *ny < self.dim.1 {
-                let neighbor_index = self.ix(*nx, *ny);
+        while let Some(i) = queue.pop_front() {
+            self.cells[i].state = CellState::Revealed;
+            result.push((i, self.cells[i].proximity));
+            self.revealed_count += 1;
 
-                // Only reveal if it's hidden, to prevent revisiting revealed cells
-                if self.cells[neighbor_index].hidden {
-                    result.extend(self.reveal(neighbor_index));
+            // Only cells without neighbouring mines open up the area around them
+            if self.cells[i].proximity > 0 {
+                continue;
+            }
+            for n in self.neighbours(i) {
+                if visited[n / 64] & (1 << (n % 64)) == 0 && self.cells[n].can_reveal() {
+                    visited[n / 64] |= 1 << (n % 64);
+                    queue.push_back(n);
                 }
             }
         }
@@ -131,20 +287,58 @@ impl BoardInstance {
     }
 
     /// Returns a list of indices where a bomb is located
-    pub fn get_bomb_positions(&self) -> Vec<u16> {
+    pub fn get_bomb_positions(&self) -> Vec<u32> {
         self.cells
             .iter()
             .enumerate()
             .filter(|(_, cell)| cell.proximity == u8::MAX)
-            .map(|(i, _)| i as u16)
+            .map(|(i, _)| i as u32)
             .collect()
     }
 
+    /// Number of mines left to find according to the flags, negative if too many were placed
+    pub fn mines_remaining(&self) -> i32 {
+        let flags = self
+            .cells
+            .iter()
+            .filter(|cell| cell.state == CellState::Flagged)
+            .count();
+        self.mine_count as i32 - flags as i32
+    }
+
+    /// Stops the timer once the game is won or lost
+    pub fn finish(&mut self) {
+        self.finished.get_or_insert_with(Instant::now);
+    }
+
+    /// Time since the first reveal, frozen once the game is finished
+    pub fn elapsed(&self) -> Duration {
+        match (self.started, self.finished) {
+            (Some(start), Some(end)) => end - start,
+            (Some(start), None) => start.elapsed(),
+            _ => Duration::ZERO,
+        }
+    }
+
     /// Returns true if all cells have been revealed
     pub fn revealed_all(&self) -> bool {
         self.cells.len() - self.revealed_count == self.mine_count
     }
 
+    /// Returns the indices of the up to 8 cells surrounding a cell
+    pub fn neighbours(&self, index: usize) -> Vec<usize> {
+        let (x, y) = self.coord_from_index(index);
+        let mut neighbours = Vec::with_capacity(8);
+        for ny in y.saturating_sub(1)..=(y + 1).min(self.dim.1 - 1) {
+            for nx in x.saturating_sub(1)..=(x + 1).min(self.dim.0 - 1) {
+                if (nx, ny) != (x, y) {
+                    neighbours.push(self.ix(nx, ny));
+                }
+            }
+        }
+        neighbours
+    }
+
     /// Given a coord it returns the corresponding cell index
     pub fn ix(&self, i: usize, j: usize) -> usize {
         //assert!(i < self.dim.0 && j < self.dim.1, "Index out of bounds");
@@ -159,3 +353,104 @@ impl BoardInstance {
         (x, y)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mines(
+        dim: (usize, usize),
+        mine_count: usize,
+        options: GameOptions,
+        first: usize,
+    ) -> Vec<u32> {
+        let seed = options.seed.unwrap();
+        let mut board = BoardInstance::init(&dim, mine_count, &options, seed);
+        assert!(board.place_mines(first));
+        board.get_bomb_positions()
+    }
+
+    #[test]
+    fn seeds_are_reproducible() {
+        let options = GameOptions {
+            seed: Some(42),
+            ..GameOptions::default()
+        };
+        let board = mines((30, 16), 99, options, 17);
+        assert_eq!(board.len(), 99);
+        assert!(!board.contains(&17));
+        assert_eq!(mines((30, 16), 99, options, 17), board);
+        // The same seed is shuffled around a different first reveal
+        assert_ne!(mines((30, 16), 99, options, 200), board);
+        let other = GameOptions {
+            seed: Some(43),
+            ..options
+        };
+        assert_ne!(mines((30, 16), 99, other, 17), board);
+    }
+
+    #[test]
+    fn no_guess_seeds_are_reproducible() {
+        let options = GameOptions {
+            no_guess: true,
+            seed: Some(7),
+            ..GameOptions::default()
+        };
+        let board = mines((16, 16), 40, options, 100);
+        for _ in 0..3 {
+            assert_eq!(mines((16, 16), 40, options, 100), board);
+        }
+    }
+
+    #[test]
+    fn reveal_returns_each_cell_once() {
+        let options = GameOptions {
+            seed: Some(3),
+            ..GameOptions::default()
+        };
+        let mut board = BoardInstance::init(&(30, 16), 20, &options, 3);
+        assert!(board.place_mines(0));
+        // A flag inside the area that opens up has to stay closed
+        let flagged = (0..board.cells.len())
+            .rev()
+            .find(|&i| board.cells[i].proximity == 0)
+            .unwrap();
+        board.cells[flagged].state = CellState::Flagged;
+
+        let revealed = board.reveal(0);
+        let mut indices: Vec<usize> = revealed.iter().map(|(i, _)| *i).collect();
+        indices.sort_unstable();
+        indices.dedup();
+        assert_eq!(indices.len(), revealed.len());
+        assert_eq!(board.revealed_count, revealed.len());
+        assert!(
+            revealed.len() > 100,
+            "Sparse board opened only {:?}",
+            revealed
+        );
+        assert_eq!(board.cells[flagged].state, CellState::Flagged);
+        assert!(board.neighbours(flagged).iter().any(|&n| {
+            board.cells[n].state == CellState::Revealed && board.cells[n].proximity == 0
+        }));
+
+        for &(i, value) in &revealed {
+            assert_eq!(value, board.cells[i].proximity);
+            assert_ne!(value, u8::MAX);
+            // Only the first cell and the neighbours of revealed zeros open
+            assert!(
+                i == 0
+                    || board.neighbours(i).iter().any(|&n| {
+                        board.cells[n].state == CellState::Revealed && board.cells[n].proximity == 0
+                    })
+            );
+            // Zeros open every neighbour that is not flagged
+            if value == 0 {
+                for n in board.neighbours(i) {
+                    assert!(n == flagged || board.cells[n].state == CellState::Revealed);
+                }
+            }
+        }
+        // Revealing an open cell again reveals nothing
+        assert!(board.reveal(0).is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+//! Server settings, read from command line flags, environment variables and a TOML file
+//!
+//! Flags take precedence over environment variables, which take precedence over the file.
+//! Anything left unset falls back to its default.
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use protocol::v3::{MAX_DIM, MIN_DIM};
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Address the server listens on unless configured otherwise
+pub const DEFAULT_BIND: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+/// Port the server listens on unless configured otherwise
+pub const DEFAULT_PORT: u16 = 8000;
+/// Largest width or height NewGame accepts unless configured otherwise
+pub const DEFAULT_MAX_DIM: usize = 100;
+/// Most connections served at once unless configured otherwise
+pub const DEFAULT_MAX_CONNECTIONS: usize = 1024;
+/// How long a connection may wait between messages unless configured otherwise
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+/// How long a game outlives its connection unless configured otherwise
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(120);
+
+/// Command line flags, each of which can also be set through its environment variable
+#[derive(Parser)]
+#[command(about = "Hosts MineSweeper games over TCP")]
+struct Args {
+    /// TOML file with the settings below, flags and environment variables override it
+    #[arg(short, long, env = "MINESWEEPER_CONFIG")]
+    config: Option<PathBuf>,
+    #[command(flatten)]
+    settings: Settings,
+}
+
+/// Settings that may be left out, used both for the flags and the file
+#[derive(clap::Args, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Settings {
+    /// Address to listen on [default: 127.0.0.1]
+    #[arg(short, long, env = "MINESWEEPER_BIND")]
+    bind: Option<IpAddr>,
+    /// Port to listen on [default: 8000]
+    #[arg(short, long, env = "MINESWEEPER_PORT")]
+    port: Option<u16>,
+    /// Largest width or height of a board, up to 1000 [default: 100]
+    #[arg(long, env = "MINESWEEPER_MAX_DIM")]
+    max_dim: Option<usize>,
+    /// Most connections served at once, further clients are turned away [default: 1024]
+    #[arg(long, env = "MINESWEEPER_MAX_CONNECTIONS")]
+    max_connections: Option<usize>,
+    /// Seconds a connection may wait between messages before it is closed [default: 600]
+    #[arg(long, env = "MINESWEEPER_IDLE_TIMEOUT")]
+    idle_timeout: Option<u64>,
+    /// Seconds a game is kept after its connection drops so it can be resumed [default: 120]
+    #[arg(long, env = "MINESWEEPER_GRACE_PERIOD")]
+    grace_period: Option<u64>,
+}
+impl Settings {
+    /// Fills the settings left unset with the ones from `other`
+    fn or(self, other: Settings) -> Settings {
+        Settings {
+            bind: self.bind.or(other.bind),
+            port: self.port.or(other.port),
+            max_dim: self.max_dim.or(other.max_dim),
+            max_connections: self.max_connections.or(other.max_connections),
+            idle_timeout: self.idle_timeout.or(other.idle_timeout),
+            grace_period: self.grace_period.or(other.grace_period),
+        }
+    }
+}
+
+/// Settings the server runs with
+pub struct Config {
+    pub addr: SocketAddr,
+    /// Largest width or height NewGame accepts
+    pub max_dim: usize,
+    pub max_connections: usize,
+    pub idle_timeout: Duration,
+    pub grace_period: Duration,
+}
+impl Config {
+    /// Reads the configuration from the command line, the environment and the config file
+    pub fn load() -> Result<Config> {
+        let args = Args::parse();
+        let file = match args.config {
+            Some(path) => read_file(&path)?,
+            None => Settings::default(),
+        };
+        Config::from_settings(args.settings.or(file))
+    }
+
+    fn from_settings(settings: Settings) -> Result<Config> {
+        let max_dim = settings.max_dim.unwrap_or(DEFAULT_MAX_DIM);
+        // Larger boards would need more memory than a game should take and replies over MAX_BYTES
+        if !(MIN_DIM..=MAX_DIM).contains(&max_dim) {
+            bail!(
+                "max_dim must be between {} and {}, got {}",
+                MIN_DIM,
+                MAX_DIM,
+                max_dim
+            );
+        }
+        let max_connections = settings.max_connections.unwrap_or(DEFAULT_MAX_CONNECTIONS);
+        if max_connections == 0 {
+            bail!("max_connections must be at least 1");
+        }
+        Ok(Config {
+            addr: SocketAddr::new(
+                settings.bind.unwrap_or(DEFAULT_BIND),
+                settings.port.unwrap_or(DEFAULT_PORT),
+            ),
+            max_dim,
+            max_connections,
+            idle_timeout: settings
+                .idle_timeout
+                .map_or(DEFAULT_IDLE_TIMEOUT, Duration::from_secs),
+            grace_period: settings
+                .grace_period
+                .map_or(DEFAULT_GRACE_PERIOD, Duration::from_secs),
+        })
+    }
+}
+
+/// Reads the settings of a TOML config file
+fn read_file(path: &Path) -> Result<Settings> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unset_settings_fall_back_in_order() {
+        let flags = Settings {
+            port: Some(9000),
+            ..Settings::default()
+        };
+        let file = Settings {
+            port: Some(9001),
+            max_dim: Some(50),
+            ..Settings::default()
+        };
+        let config = Config::from_settings(flags.or(file)).unwrap();
+        assert_eq!(config.addr, SocketAddr::new(DEFAULT_BIND, 9000));
+        assert_eq!(config.max_dim, 50);
+        assert_eq!(config.max_connections, DEFAULT_MAX_CONNECTIONS);
+        assert_eq!(config.idle_timeout, DEFAULT_IDLE_TIMEOUT);
+        assert_eq!(config.grace_period, DEFAULT_GRACE_PERIOD);
+    }
+
+    #[test]
+    fn flags_override_the_environment_which_overrides_the_file() {
+        // No other test parses the command line so the variables can not leak into one
+        std::env::set_var("MINESWEEPER_PORT", "9001");
+        std::env::set_var("MINESWEEPER_GRACE_PERIOD", "30");
+        let args = Args::try_parse_from(["server", "--port", "9000"]);
+        std::env::remove_var("MINESWEEPER_PORT");
+        std::env::remove_var("MINESWEEPER_GRACE_PERIOD");
+
+        let file: Settings =
+            toml::from_str("port = 9002\ngrace_period = 60\nidle_timeout = 5").unwrap();
+        let config = Config::from_settings(args.unwrap().settings.or(file)).unwrap();
+        assert_eq!(config.addr.port(), 9000);
+        assert_eq!(config.grace_period, Duration::from_secs(30));
+        assert_eq!(config.idle_timeout, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn limits_are_checked() {
+        let with = |max_dim, max_connections| {
+            Config::from_settings(Settings {
+                max_dim: Some(max_dim),
+                max_connections: Some(max_connections),
+                ..Settings::default()
+            })
+        };
+        assert!(with(MIN_DIM, 1).is_ok());
+        assert!(with(MAX_DIM, 1).is_ok());
+        assert!(with(MIN_DIM - 1, 1).is_err());
+        assert!(with(MAX_DIM + 1, 1).is_err());
+        assert!(with(u16::MAX as usize, 1).is_err());
+        assert!(with(MAX_DIM, 0).is_err());
+    }
+
+    #[test]
+    fn config_files_reject_unknown_settings() {
+        let path = std::env::temp_dir().join(format!("minesweeper-{}.toml", std::process::id()));
+        std::fs::write(&path, "port = 9000\nmax_dims = 50\n").unwrap();
+        let result = read_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let err = result.err().expect("Typo in the config file was accepted");
+        assert!(format!("{:#}", err).contains("max_dims"));
+    }
+}
@@ -0,0 +1,362 @@
+//! A game as played over a connection, kept apart from the connection so it can outlive it
+
+use protocol::v3::{
+    BoardState, GameOptions, GameStatus, Reveals, ServerMsg, CELL_FLAGGED, CELL_HIDDEN, CELL_MINE,
+    CELL_QUESTION, MIN_DIM, MIN_MINES,
+};
+use protocol::ErrorCode;
+use server::board::{BoardInstance, CellState, MAX_NO_GUESS_CELLS};
+use std::time::Duration;
+
+/// Represents the games current state
+#[derive(PartialEq)]
+pub enum State {
+    Playing,
+    Idle,
+    Lost,
+    Won,
+}
+
+/// The board of a session and how far along it is
+pub struct Game {
+    pub board: Option<BoardInstance>,
+    pub state: State,
+}
+impl Default for Game {
+    fn default() -> Self {
+        Game {
+            board: None,
+            state: State::Idle,
+        }
+    }
+}
+impl Game {
+    /// Copy of the board if revealing `index` first needs mines placed, None if it does not
+    ///
+    /// Lets the mines be placed without holding on to the game, see `place_mines`.
+    pub fn unplaced_board(&self, index: usize) -> Option<BoardInstance> {
+        let board = self.board.as_ref()?;
+        let ready = self.state == State::Playing
+            && index < board.cells.len()
+            && board.cells[index].state != CellState::Flagged;
+        (ready && !board.mines_placed).then(|| board.clone())
+    }
+    /// Reveals `index` on the board from `unplaced_board` once mines were placed on it
+    ///
+    /// `placed` is the result of placing the mines. Returns None if the game was changed
+    /// meanwhile and the mines do not belong to it.
+    pub fn reveal_placed(
+        &mut self,
+        index: usize,
+        board: BoardInstance,
+        placed: bool,
+    ) -> Option<ServerMsg> {
+        let current = self.board.as_mut()?;
+        let same_game = self.state == State::Playing
+            && !current.mines_placed
+            && current.dim == board.dim
+            && current.mine_count == board.mine_count
+            && current.options == board.options
+            && current.seed == board.seed;
+        if !same_game {
+            return None;
+        }
+        if !placed {
+            return Some(ServerMsg::Error(ErrorCode::GenerationFailed));
+        }
+        // Marks may have changed while the mines were placed so only the mines are taken
+        for (cell, placed) in current.cells.iter_mut().zip(board.cells) {
+            cell.proximity = placed.proximity;
+        }
+        current.mines_placed = true;
+        Some(self.reveal(index))
+    }
+    pub fn reveal(&mut self, index: usize) -> ServerMsg {
+        let Some(ref mut board) = self.board else {
+            return ServerMsg::Error(ErrorCode::NoGameInProgress);
+        };
+        if self.state != State::Playing {
+            return ServerMsg::Error(ErrorCode::GameOver);
+        }
+        if index >= board.cells.len() {
+            return ServerMsg::Error(ErrorCode::IndexOutOfRange);
+        }
+        if board.cells[index].state == CellState::Flagged {
+            return ServerMsg::Error(ErrorCode::CellFlagged);
+        }
+        if !board.mines_placed && !board.place_mines(index) {
+            return ServerMsg::Error(ErrorCode::GenerationFailed);
+        }
+
+        let revealed = board.reveal_cells(index);
+        Self::finish_move(&mut self.state, board, revealed)
+    }
+    pub fn chord(&mut self, index: usize) -> ServerMsg {
+        let Some(ref mut board) = self.board else {
+            return ServerMsg::Error(ErrorCode::NoGameInProgress);
+        };
+        if self.state != State::Playing {
+            return ServerMsg::Error(ErrorCode::GameOver);
+        }
+        if index >= board.cells.len() {
+            return ServerMsg::Error(ErrorCode::IndexOutOfRange);
+        }
+
+        let revealed = board.chord_cells(index);
+        Self::finish_move(&mut self.state, board, revealed)
+    }
+    /// Places or clears a flag or question mark on a hidden cell
+    pub fn mark(&mut self, index: usize, mark: CellState) -> ServerMsg {
+        let Some(ref mut board) = self.board else {
+            return ServerMsg::Error(ErrorCode::NoGameInProgress);
+        };
+        if self.state != State::Playing {
+            return ServerMsg::Error(ErrorCode::GameOver);
+        }
+        if index >= board.cells.len() {
+            return ServerMsg::Error(ErrorCode::IndexOutOfRange);
+        }
+        if board.cells[index].state == CellState::Revealed {
+            return ServerMsg::Error(ErrorCode::CellRevealed);
+        }
+
+        board.cells[index].state = mark;
+        ServerMsg::MinesRemaining(board.mines_remaining())
+    }
+    /// Turns the cells revealed by a move into the reply, ending the game if it was won or lost
+    fn finish_move(
+        state: &mut State,
+        board: &mut BoardInstance,
+        revealed: Option<Vec<(usize, u8)>>,
+    ) -> ServerMsg {
+        let Some(revealed) = revealed else {
+            *state = State::Lost;
+            board.finish();
+            return ServerMsg::GameLoss(format_time(board.elapsed()), board.get_bomb_positions());
+        };
+        let revealed: Reveals = revealed.into_iter().collect();
+        if board.revealed_all() {
+            *state = State::Won;
+            board.finish();
+            ServerMsg::GameWin(format_time(board.elapsed()), revealed)
+        } else {
+            ServerMsg::RevealCells(revealed)
+        }
+    }
+    pub fn new_game(
+        &mut self,
+        max_dim: usize,
+        width: usize,
+        height: usize,
+        mine_count: usize,
+        options: GameOptions,
+    ) -> ServerMsg {
+        if width > max_dim || height > max_dim {
+            return ServerMsg::Error(ErrorCode::BoardTooLarge);
+        }
+        // Larger no-guess boards can not be generated within the budget
+        if options.no_guess && width * height > MAX_NO_GUESS_CELLS {
+            return ServerMsg::Error(ErrorCode::BoardTooLarge);
+        }
+        if width < MIN_DIM || height < MIN_DIM {
+            return ServerMsg::Error(ErrorCode::BoardTooSmall);
+        }
+        if mine_count < MIN_MINES {
+            return ServerMsg::Error(ErrorCode::TooFewMines);
+        }
+        // At least one cell has to be free of mines for the game to be winnable
+        if mine_count >= width * height {
+            return ServerMsg::Error(ErrorCode::TooManyMines);
+        }
+
+        let seed = options.seed.unwrap_or_else(rand::random);
+        self.board = Some(BoardInstance::init(
+            &(width, height),
+            mine_count,
+            &options,
+            seed,
+        ));
+        self.state = State::Playing;
+        ServerMsg::GameCreated(seed)
+    }
+    pub fn get_time(&self) -> ServerMsg {
+        match self.board {
+            Some(ref board) => ServerMsg::Time(format_time(board.elapsed())),
+            None => ServerMsg::Error(ErrorCode::NoGameInProgress),
+        }
+    }
+    pub fn close_game(&mut self) -> ServerMsg {
+        self.state = State::Idle;
+        self.board = None;
+        ServerMsg::Accepted()
+    }
+    /// Everything a client needs to show the game again, None if there is no game
+    pub fn board_state(&self) -> Option<BoardState> {
+        let board = self.board.as_ref()?;
+        let status = match self.state {
+            State::Won => GameStatus::Won,
+            State::Lost => GameStatus::Lost,
+            State::Playing | State::Idle => GameStatus::Playing,
+        };
+        let cells = board
+            .cells
+            .iter()
+            .map(|cell| match cell.state {
+                CellState::Revealed => cell.proximity,
+                // Mines are only given away once the game is over
+                _ if cell.proximity == u8::MAX && status != GameStatus::Playing => CELL_MINE,
+                CellState::Hidden => CELL_HIDDEN,
+                CellState::Flagged => CELL_FLAGGED,
+                CellState::Question => CELL_QUESTION,
+            })
+            .collect();
+        Some(BoardState {
+            width: board.dim.0 as u32,
+            height: board.dim.1 as u32,
+            cells,
+            status,
+            time: format_time(board.elapsed()),
+            seed: board.seed,
+            mines_remaining: board.mines_remaining(),
+        })
+    }
+}
+
+/// Formats a game duration as seconds with millisecond precision
+fn format_time(time: Duration) -> String {
+    format!("{:.3}", time.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A game being played on a board drawn with `*` for mines and `.` for safe cells
+    fn game(rows: &[&str]) -> Game {
+        let dim = (rows[0].len(), rows.len());
+        let cells: Vec<u8> = rows.iter().flat_map(|row| row.bytes()).collect();
+        let mine_count = cells.iter().filter(|&&c| c == b'*').count();
+        let mut board = BoardInstance::init(&dim, mine_count, &GameOptions::default(), 0);
+        for (i, cell) in cells.iter().enumerate() {
+            if *cell == b'*' {
+                board.cells[i].proximity = u8::MAX;
+            }
+        }
+        for i in 0..board.cells.len() {
+            if board.cells[i].proximity != u8::MAX {
+                let mines = board
+                    .neighbours(i)
+                    .into_iter()
+                    .filter(|n| board.cells[*n].proximity == u8::MAX)
+                    .count();
+                board.cells[i].proximity = mines as u8;
+            }
+        }
+        board.mines_placed = true;
+        Game {
+            board: Some(board),
+            state: State::Playing,
+        }
+    }
+
+    /// Indices revealed by a move that neither won nor lost
+    fn revealed(reply: ServerMsg) -> Vec<usize> {
+        let ServerMsg::RevealCells(cells) = reply else {
+            panic!("Move did not just reveal cells");
+        };
+        let mut indices: Vec<usize> = cells.iter().map(|(i, _)| i).collect();
+        indices.sort_unstable();
+        indices
+    }
+
+    // The 2 at index 5 touches the mines at 0 and 10, the mine at 7 keeps the game going
+    const CHORD: [&str; 3] = [
+        "*...", //
+        "...*", //
+        "..*.", //
+    ];
+
+    #[test]
+    fn chords_need_as_many_flags_as_the_number() {
+        let mut game = game(&CHORD);
+        assert_eq!(revealed(game.reveal(5)), vec![5]);
+        assert!(revealed(game.chord(5)).is_empty());
+        game.mark(0, CellState::Flagged);
+        assert!(revealed(game.chord(5)).is_empty());
+        // Hidden cells can not be chorded
+        assert!(revealed(game.chord(1)).is_empty());
+        let board = game.board.as_ref().unwrap();
+        assert_eq!(board.revealed_count, 1);
+    }
+
+    #[test]
+    fn chords_reveal_the_unflagged_neighbours() {
+        let mut game = game(&CHORD);
+        game.reveal(5);
+        game.mark(0, CellState::Flagged);
+        game.mark(10, CellState::Flagged);
+        // Question marks do not protect a cell from a chord
+        game.mark(1, CellState::Question);
+        assert_eq!(revealed(game.chord(5)), vec![1, 2, 4, 6, 8, 9]);
+        let board = game.board.as_ref().unwrap();
+        assert_eq!(board.cells[1].state, CellState::Revealed);
+        assert_eq!(board.cells[0].state, CellState::Flagged);
+        assert!(game.state == State::Playing);
+    }
+
+    #[test]
+    fn chords_around_a_wrong_flag_lose() {
+        let mut game = game(&CHORD);
+        game.reveal(5);
+        game.mark(0, CellState::Flagged);
+        game.mark(1, CellState::Flagged);
+        let ServerMsg::GameLoss(_, mines) = game.chord(5) else {
+            panic!("Chord did not hit the mine");
+        };
+        assert_eq!(mines, vec![0, 7, 10]);
+        assert!(game.state == State::Lost);
+        assert!(game.chord(5) == ServerMsg::Error(ErrorCode::GameOver));
+    }
+
+    #[test]
+    fn marks_count_flags_against_the_mines() {
+        let mut game = game(&CHORD);
+        assert!(game.mark(4, CellState::Flagged) == ServerMsg::MinesRemaining(2));
+        // Question marks do not count as flags
+        assert!(game.mark(6, CellState::Question) == ServerMsg::MinesRemaining(2));
+        for i in [1, 2, 3] {
+            game.mark(i, CellState::Flagged);
+        }
+        // More flags than mines leave a negative count
+        assert!(game.mark(8, CellState::Flagged) == ServerMsg::MinesRemaining(-2));
+        assert!(game.mark(8, CellState::Hidden) == ServerMsg::MinesRemaining(-1));
+        assert!(game.mark(6, CellState::Hidden) == ServerMsg::MinesRemaining(-1));
+        assert_eq!(
+            game.board.as_ref().unwrap().cells[6].state,
+            CellState::Hidden
+        );
+    }
+
+    #[test]
+    fn revealed_cells_can_not_be_marked() {
+        let mut game = game(&CHORD);
+        game.reveal(5);
+        for mark in [CellState::Flagged, CellState::Question, CellState::Hidden] {
+            assert!(game.mark(5, mark) == ServerMsg::Error(ErrorCode::CellRevealed));
+        }
+        assert!(game.mark(12, CellState::Flagged) == ServerMsg::Error(ErrorCode::IndexOutOfRange));
+    }
+
+    #[test]
+    fn flags_protect_a_cell_from_reveal() {
+        let mut game = game(&CHORD);
+        game.mark(0, CellState::Flagged);
+        assert!(game.reveal(0) == ServerMsg::Error(ErrorCode::CellFlagged));
+        assert!(game.state == State::Playing);
+        // Question marks do not
+        game.mark(4, CellState::Question);
+        assert_eq!(revealed(game.reveal(4)), vec![4]);
+        game.mark(0, CellState::Hidden);
+        assert!(matches!(game.reveal(0), ServerMsg::GameLoss(..)));
+    }
+}
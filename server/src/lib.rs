@@ -0,0 +1,2 @@
+//! Game logic of the MineSweeper server, kept apart from the networking so it can be benchmarked
+pub mod board;
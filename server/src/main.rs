@@ -1,134 +1,374 @@
-use tokio::io::{split, AsyncReadExt, AsyncWriteExt, BufReader};
+use anyhow::{bail, Context, Result};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{split, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::{TcpListener, TcpStream};
-mod board;
-mod protocol_v2;
-use protocol_v2::{ClientMsg, ServerMsg};
-mod zip;
-use board::BoardInstance;
-
-/// Represents the games current state
-#[derive(PartialEq)]
-pub enum State {
-    Playing,
-    Idle,
-    Lost,
-    Won,
-}
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+mod config;
+mod game;
+mod session;
+mod v1;
+mod v2;
+use config::Config;
+use protocol::frame::{self, FrameHeader};
+use protocol::v2::MAX_BYTES;
+use protocol::v3::{Bytes, ClientMsg, ServerMsg};
+use protocol::{ErrorCode, Message};
+use server::board::CellState;
+use session::{lock, Sessions, SharedGame};
+
+/// Protocol versions this server can speak, selected per connection with SetVersion
+pub const SUPPORTED_VERSIONS: [u16; 3] = [1, 2, 3];
+
 struct ClientHandler {
     pub version: u16,
-    pub board: Option<BoardInstance>,
-    pub state: State,
+    pub game: SharedGame,
+    /// Token of the session this connection owns, only v3 connections have one
+    pub session: Option<u64>,
+    /// Identifies this connection as the owner of its session
+    pub id: u64,
+    pub sessions: Arc<Sessions>,
+    /// Largest width or height this server accepts from v3 clients
+    pub max_dim: usize,
 }
 impl ClientHandler {
-    pub fn new() -> Self {
+    pub fn new(max_dim: usize, sessions: Arc<Sessions>) -> Self {
         ClientHandler {
-            version: 0,
-            board: None,
-            state: State::Idle,
+            version: protocol::HANDSHAKE_VERSION,
+            game: SharedGame::default(),
+            session: None,
+            id: rand::random(),
+            sessions,
+            max_dim,
+        }
+    }
+    /// Handles a request and returns the reply for the client, fails if the session has to end
+    pub async fn handle(&mut self, msg: ClientMsg) -> Result<ServerMsg> {
+        if let Some(token) = self.session {
+            if !self.sessions.owns(token, self.id) {
+                return Ok(ServerMsg::Error(ErrorCode::SessionTakenOver));
+            }
+        }
+        if let ClientMsg::Reveal(index) = msg {
+            return self.reveal(index as usize).await;
+        }
+        let mut game = lock(&self.game);
+        let reply = match msg {
+            ClientMsg::Error(code) => bail!("Client reported {}", code),
+            ClientMsg::SetVersion(version) => {
+                drop(game);
+                self.set_version(version)
+            }
+            ClientMsg::Resume(token) => {
+                drop(game);
+                self.resume(token)
+            }
+            ClientMsg::Reveal(_) => unreachable!("Reveal is handled before locking the game"),
+            ClientMsg::NewGame(width, height, mine_count, options) => game.new_game(
+                self.max_dim(),
+                width as usize,
+                height as usize,
+                mine_count as usize,
+                options,
+            ),
+            ClientMsg::GetTime() => game.get_time(),
+            ClientMsg::CloseGame() => game.close_game(),
+            ClientMsg::GetVersions() => ServerMsg::Versions(SUPPORTED_VERSIONS.to_vec()),
+            ClientMsg::Chord(index) => game.chord(index as usize),
+            ClientMsg::Flag(index) => game.mark(index as usize, CellState::Flagged),
+            ClientMsg::Unflag(index) => game.mark(index as usize, CellState::Hidden),
+            ClientMsg::Mark(index) => game.mark(index as usize, CellState::Question),
+        };
+        Ok(reply)
+    }
+    /// Largest width or height of a board, older versions keep their replies within v2::MAX_BYTES
+    pub fn max_dim(&self) -> usize {
+        match self.version {
+            1 | 2 => self.max_dim.min(protocol::v2::MAX_DIM),
+            _ => self.max_dim,
         }
     }
     pub fn set_version(&mut self, version: u16) -> ServerMsg {
+        if !SUPPORTED_VERSIONS.contains(&version) {
+            return ServerMsg::Error(ErrorCode::UnsupportedVersion);
+        }
         self.version = version;
-        self.board = None;
-        self.state = State::Idle;
-        ServerMsg::Accepted()
-    }
-    pub fn reveal(&mut self, index: usize) -> ServerMsg {
-        if let Some(ref mut board) = self.board {
-            let revealed = board.reveal_cells(index);
-            if revealed.len() == 0 {
-                ServerMsg::GameLoss(board.get_bomb_positions())
-            } else if board.revealed_all() {
-                ServerMsg::GameWin(revealed)
-            } else {
-                ServerMsg::RevealCells(revealed)
+        self.game = SharedGame::default();
+        if let Some(token) = self.session.take() {
+            self.sessions.close(token);
+        }
+        // Only v3 can resume sessions so older versions keep their game to themselves
+        if version < 3 {
+            return ServerMsg::Accepted();
+        }
+        let token = self.sessions.open(self.id, self.game.clone());
+        self.session = Some(token);
+        ServerMsg::SessionAccepted(token)
+    }
+    /// Reveals a cell, placing the mines on a blocking thread first if this is the first reveal
+    ///
+    /// The game is not locked while the mines are placed so the session stays responsive.
+    async fn reveal(&mut self, index: usize) -> Result<ServerMsg> {
+        loop {
+            let unplaced = lock(&self.game).unplaced_board(index);
+            let Some(mut board) = unplaced else {
+                return Ok(lock(&self.game).reveal(index));
+            };
+            let (board, placed) = tokio::task::spawn_blocking(move || {
+                let placed = board.place_mines(index);
+                (board, placed)
+            })
+            .await
+            .context("Mine placement panicked")?;
+
+            // Another connection of the session may have changed the game meanwhile
+            if let Some(reply) = lock(&self.game).reveal_placed(index, board, placed) {
+                return Ok(reply);
             }
-        } else {
-            ServerMsg::Error(100)
         }
     }
-    pub fn new_game(&mut self, width: usize, height: usize, mine_count: usize) -> ServerMsg {
-        self.board = Some(BoardInstance::init(&(width, height), mine_count));
-        self.state = State::Playing;
-        ServerMsg::Accepted()
+    /// Takes over the game of another session, ending the session this connection had
+    pub fn resume(&mut self, token: u64) -> ServerMsg {
+        let Some(game) = self.sessions.resume(token, self.id) else {
+            return ServerMsg::Error(ErrorCode::SessionExpired);
+        };
+        if let Some(own) = self.session.replace(token) {
+            if own != token {
+                self.sessions.close(own);
+            }
+        }
+        self.game = game;
+        ServerMsg::Resumed(lock(&self.game).board_state())
+    }
+}
+
+/// Decodes a request with the codec of the negotiated version
+fn decode(version: u16, header: &FrameHeader, payload: &Bytes) -> Result<ClientMsg> {
+    match version {
+        1 => protocol::v1::ClientMsg::from_frame(header, payload).map(v1::upgrade),
+        2 => protocol::v2::ClientMsg::from_frame(header, payload).map(v2::upgrade),
+        _ => ClientMsg::from_frame(header, payload),
     }
-    pub fn close_game(&mut self) -> ServerMsg {
-        self.state = State::Idle;
-        self.board = None;
-        ServerMsg::Accepted()
+}
+
+/// Encodes a reply with the codec of the negotiated version
+fn encode(version: u16, msg: ServerMsg) -> Result<Bytes> {
+    match version {
+        1 => v1::downgrade(msg).to_frame(),
+        2 => v2::downgrade(msg).to_frame(),
+        _ => msg.to_frame(),
     }
 }
 
-pub async fn handle(mut socket: TcpStream) {
+/// Writes a reply to the client, falling back to an error reply if it can not be encoded
+///
+/// Fails if the client does not take the reply within `deadline`, so a client that stops
+/// reading can not hold on to the connection.
+async fn send<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    version: u16,
+    msg: ServerMsg,
+    deadline: Duration,
+) -> Result<()> {
+    let bytes = match encode(version, msg) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            let error = encode(version, ServerMsg::Error(ErrorCode::RecipientUnrecoverable))?;
+            write(writer, &error, deadline).await?;
+            return Err(err.context("Failed to encode reply"));
+        }
+    };
+    write(writer, &bytes, deadline).await
+}
+
+/// Writes bytes to the client, failing if they are not taken within `deadline`
+async fn write<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    bytes: &[u8],
+    deadline: Duration,
+) -> Result<()> {
+    match timeout(deadline, writer.write_all(bytes)).await {
+        Ok(result) => result.context("Failed to write to socket"),
+        Err(_) => bail!("Client did not read a reply for longer than {:?}", deadline),
+    }
+}
+
+/// Serves a single connection, keeping its game for a while if the connection drops
+pub async fn handle(socket: TcpStream, config: &Config, sessions: Arc<Sessions>) -> Result<()> {
+    let mut client_handler = ClientHandler::new(config.max_dim, sessions.clone());
+    let result = serve(socket, &mut client_handler, config.idle_timeout).await;
+    if let Some(token) = client_handler.session {
+        sessions.detach(token, client_handler.id);
+    }
+    result
+}
+
+/// Serves requests until the client leaves or the session fails
+async fn serve(
+    mut socket: TcpStream,
+    client_handler: &mut ClientHandler,
+    idle_timeout: Duration,
+) -> Result<()> {
     let (reader, mut writer) = split(&mut socket);
     let mut reader = BufReader::new(reader);
-    let mut buffer = vec![0; 2048];
 
-    let mut client_handler = ClientHandler::new();
     loop {
-        match reader.read(&mut buffer).await {
-            Ok(size) => {
-                if size == 0 {
-                    break;
-                }
-                let msg = ClientMsg::from_bytes(&buffer).expect("");
-                let response: ServerMsg = match msg {
-                    ClientMsg::Error(code) => panic!("Error Code Received: {}", code),
-                    ClientMsg::SetVersion(version) => {
-                        client_handler.set_version(version)
-                    },
-                    ClientMsg::Reveal(index) => {
-                        client_handler.reveal(index as usize)
-                    }
-                    ClientMsg::NewGame(width, height, mine_count) => {
-                        client_handler.new_game(width as usize, height as usize, mine_count as usize)
-                    },
-                    ClientMsg::GetTime() => ServerMsg::Accepted(),
-                    ClientMsg::CloseGame() => {
-                        client_handler.close_game()
-                    },
-                };
-
-                let bytes = response.to_bytes().unwrap();
-                writer
-                    .write_all(&bytes)
-                    .await
-                    .expect("Failed to write to socket");
+        // Replies are encoded with the codec the request arrived in
+        let version = client_handler.version;
+
+        // Requests are small in every version so they are all held to the v2 limit
+        let frame = timeout(
+            idle_timeout,
+            frame::read_frame_async(&mut reader, MAX_BYTES),
+        )
+        .await;
+        let (header, payload) = match frame {
+            Ok(Ok(Some(frame))) => frame,
+            Ok(Ok(None)) => return Ok(()),
+            Ok(Err(err)) if err.kind() == io::ErrorKind::InvalidData => {
+                send(
+                    &mut writer,
+                    version,
+                    ServerMsg::Error(ErrorCode::MalformedFrame),
+                    idle_timeout,
+                )
+                .await?;
+                return Err(err).context("Rejected frame");
+            }
+            Ok(Err(err)) => return Err(err).context("Failed to read from socket"),
+            Err(_) => {
+                send(
+                    &mut writer,
+                    version,
+                    ServerMsg::Error(ErrorCode::IdleTimeout),
+                    idle_timeout,
+                )
+                .await?;
+                bail!("Connection was idle for longer than {:?}", idle_timeout);
             }
-            Err(err) => match err.kind() {
-                std::io::ErrorKind::NotFound
-                | std::io::ErrorKind::PermissionDenied
-                | std::io::ErrorKind::ConnectionRefused
-                | std::io::ErrorKind::ConnectionReset
-                | std::io::ErrorKind::ConnectionAborted
-                | std::io::ErrorKind::NotConnected
-                | std::io::ErrorKind::AddrNotAvailable
-                | std::io::ErrorKind::BrokenPipe
-                | std::io::ErrorKind::AlreadyExists
-                | std::io::ErrorKind::TimedOut => panic!("{:?}", err.kind()),
-                _ => continue,
-            },
+        };
+
+        // The frame was read completely so the stream is still in sync after a bad payload
+        let reply = match decode(version, &header, &payload) {
+            Ok(msg) => client_handler.handle(msg).await?,
+            Err(err) => {
+                println!("Malformed message: {:#}", err);
+                ServerMsg::Error(ErrorCode::MalformedFrame)
+            }
+        };
+        let taken_over = reply == ServerMsg::Error(ErrorCode::SessionTakenOver);
+        send(&mut writer, version, reply, idle_timeout).await?;
+        if taken_over {
+            bail!("Session was resumed on another connection");
         }
     }
 }
 
 #[tokio::main]
 async fn main() {
-    println!("Starting Server");
-    let listener = TcpListener::bind("127.0.0.1:8000")
+    let config = match Config::load() {
+        Ok(config) => Arc::new(config),
+        Err(err) => {
+            eprintln!("{:#}", err);
+            std::process::exit(2);
+        }
+    };
+    println!("Starting Server on {}", config.addr);
+    println!("Boards may be up to {}x{}", config.max_dim, config.max_dim);
+    println!(
+        "Games are kept for {:?} after a connection drops",
+        config.grace_period
+    );
+    let sessions = Arc::new(Sessions::new(config.grace_period));
+    let connections = Arc::new(Semaphore::new(config.max_connections));
+    let listener = TcpListener::bind(config.addr)
         .await
         .expect("Error starting the server");
 
     loop {
-        let (socket, _) = listener
-            .accept()
-            .await
-            .expect("Failed to accept connection");
+        let (mut socket, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                println!("Failed to accept connection: {}", err);
+                continue;
+            }
+        };
+        let Ok(permit) = connections.clone().try_acquire_owned() else {
+            println!("Turning away {}, too many connections", peer);
+            // Nothing has been negotiated yet so the error is sent with the handshake version
+            let error = ServerMsg::Error(ErrorCode::ServerFull);
+            let deadline = config.idle_timeout;
+            tokio::spawn(async move {
+                send(&mut socket, protocol::HANDSHAKE_VERSION, error, deadline).await
+            });
+            continue;
+        };
 
+        let sessions = sessions.clone();
+        let config = config.clone();
         tokio::spawn(async move {
-            println!("Received Connection");
-            handle(socket).await;
-            println!("Connection complete")
+            println!("Received Connection from {}", peer);
+            match handle(socket, &config, sessions).await {
+                Ok(()) => println!("Connection with {} complete", peer),
+                Err(err) => println!("Connection with {} ended: {:#}", peer, err),
+            }
+            drop(permit);
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::v3::GameOptions;
+
+    fn handler(sessions: &Arc<Sessions>) -> ClientHandler {
+        ClientHandler::new(100, sessions.clone())
+    }
+
+    async fn open_session(handler: &mut ClientHandler) -> u64 {
+        match handler.handle(ClientMsg::SetVersion(3)).await.unwrap() {
+            ServerMsg::SessionAccepted(token) => token,
+            _ => panic!("No session was opened"),
+        }
+    }
+
+    #[tokio::test]
+    async fn resumed_sessions_are_taken_over() {
+        let sessions = Arc::new(Sessions::new(Duration::from_secs(60)));
+        let mut old = handler(&sessions);
+        let token = open_session(&mut old).await;
+        let reply = old
+            .handle(ClientMsg::NewGame(9, 9, 10, GameOptions::default()))
+            .await
+            .unwrap();
+        assert!(matches!(reply, ServerMsg::GameCreated(_)));
+
+        let mut new = handler(&sessions);
+        let own = open_session(&mut new).await;
+        let reply = new.handle(ClientMsg::Resume(token)).await.unwrap();
+        assert!(matches!(reply, ServerMsg::Resumed(Some(_))));
+        assert_eq!(new.session, Some(token));
+        // The session the new connection opened itself was closed
+        assert!(!sessions.owns(own, new.id));
+
+        let reply = old.handle(ClientMsg::GetTime()).await.unwrap();
+        assert!(reply == ServerMsg::Error(ErrorCode::SessionTakenOver));
+        assert!(matches!(
+            new.handle(ClientMsg::GetTime()).await.unwrap(),
+            ServerMsg::Time(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn unknown_sessions_can_not_be_resumed() {
+        let sessions = Arc::new(Sessions::new(Duration::from_secs(60)));
+        let mut handler = handler(&sessions);
+        let own = open_session(&mut handler).await;
+        let reply = handler.handle(ClientMsg::Resume(own ^ 1)).await.unwrap();
+        assert!(reply == ServerMsg::Error(ErrorCode::SessionExpired));
+        assert_eq!(handler.session, Some(own));
+    }
+}
@@ -0,0 +1,214 @@
+//! Sessions which keep a game alive after its connection drops so a client can resume it
+//!
+//! Every v3 connection opens a session and is told its token. When the connection ends
+//! the game is kept for a grace period, during which a new connection may take it over
+//! with Resume. A session can also be taken over while its old connection still looks
+//! alive, the old connection is closed the next time it makes a request.
+
+use crate::game::Game;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Most cells the boards of detached sessions may hold together, the oldest are dropped past it
+///
+/// Live connections are bounded by max_connections but a client could otherwise park any
+/// number of games by disconnecting from each of them.
+const MAX_DETACHED_CELLS: usize = 1 << 24;
+
+/// Game shared between the connections of a session
+pub type SharedGame = Arc<Mutex<Game>>;
+
+/// Locks a game, a connection that panicked while holding it leaves the game as it was
+pub fn lock(game: &SharedGame) -> MutexGuard<'_, Game> {
+    game.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+struct Session {
+    game: SharedGame,
+    /// Connection allowed to use the game
+    owner: u64,
+    /// Set once the owner disconnected, the session expires a grace period later
+    detached_at: Option<Instant>,
+    /// Cells of the board the session held when it was detached
+    cells: usize,
+}
+
+/// All sessions of the server, shared by every connection
+pub struct Sessions {
+    sessions: Mutex<HashMap<u64, Session>>,
+    /// How long a game is kept after its connection ended
+    grace_period: Duration,
+    max_detached_cells: usize,
+}
+impl Sessions {
+    pub fn new(grace_period: Duration) -> Self {
+        Sessions {
+            sessions: Mutex::new(HashMap::new()),
+            grace_period,
+            max_detached_cells: MAX_DETACHED_CELLS,
+        }
+    }
+
+    /// Locks the sessions, dropping the ones whose grace period ran out
+    fn sessions(&self) -> MutexGuard<'_, HashMap<u64, Session>> {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        sessions.retain(|_, session| {
+            session
+                .detached_at
+                .is_none_or(|at| at.elapsed() < self.grace_period)
+        });
+        sessions
+    }
+
+    /// Opens a session for a connection and returns its token
+    pub fn open(&self, owner: u64, game: SharedGame) -> u64 {
+        let mut sessions = self.sessions();
+        let mut token = rand::random();
+        while sessions.contains_key(&token) {
+            token = rand::random();
+        }
+        sessions.insert(
+            token,
+            Session {
+                game,
+                owner,
+                detached_at: None,
+                cells: 0,
+            },
+        );
+        token
+    }
+
+    /// Hands the game of a session to a new owner, None if the session is unknown or expired
+    pub fn resume(&self, token: u64, owner: u64) -> Option<SharedGame> {
+        let mut sessions = self.sessions();
+        let session = sessions.get_mut(&token)?;
+        session.owner = owner;
+        session.detached_at = None;
+        Some(session.game.clone())
+    }
+
+    /// Returns true if the connection still owns the session
+    pub fn owns(&self, token: u64, owner: u64) -> bool {
+        self.sessions()
+            .get(&token)
+            .is_some_and(|session| session.owner == owner)
+    }
+
+    /// Called when the owner disconnects, keeps the game for the grace period if there is one
+    pub fn detach(&self, token: u64, owner: u64) {
+        let mut sessions = self.sessions();
+        let Some(session) = sessions.get_mut(&token) else {
+            return;
+        };
+        if session.owner != owner {
+            return;
+        }
+        let Some(cells) = lock(&session.game).board.as_ref().map(|b| b.cells.len()) else {
+            sessions.remove(&token);
+            return;
+        };
+        session.detached_at = Some(Instant::now());
+        session.cells = cells;
+
+        // Makes room by dropping the sessions that were detached the longest
+        let mut detached: Vec<(Instant, u64, usize)> = sessions
+            .iter()
+            .filter_map(|(token, s)| s.detached_at.map(|at| (at, *token, s.cells)))
+            .collect();
+        detached.sort_unstable();
+        let mut total: usize = detached.iter().map(|(_, _, cells)| cells).sum();
+        for (_, token, cells) in detached {
+            if total <= self.max_detached_cells {
+                break;
+            }
+            sessions.remove(&token);
+            total -= cells;
+        }
+    }
+
+    /// Ends a session right away
+    pub fn close(&self, token: u64) {
+        self.sessions().remove(&token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::v3::GameOptions;
+
+    /// A game with a board of `width` x 10 cells
+    fn game(width: usize) -> SharedGame {
+        let game = SharedGame::default();
+        lock(&game).new_game(100, width, 10, 1, GameOptions::default());
+        game
+    }
+
+    #[test]
+    fn detached_sessions_expire_after_the_grace_period() {
+        let sessions = Sessions::new(Duration::from_millis(50));
+        let token = sessions.open(1, game(10));
+        sessions.detach(token, 1);
+        assert!(sessions.resume(token, 2).is_some());
+        sessions.detach(token, 2);
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(sessions.resume(token, 3).is_none());
+    }
+
+    #[test]
+    fn live_sessions_do_not_expire() {
+        let sessions = Sessions::new(Duration::ZERO);
+        let token = sessions.open(1, game(10));
+        assert!(sessions.owns(token, 1));
+    }
+
+    #[test]
+    fn resuming_takes_the_session_over() {
+        let sessions = Sessions::new(Duration::from_secs(60));
+        let shared = game(10);
+        let token = sessions.open(1, shared.clone());
+        let resumed = sessions.resume(token, 2).unwrap();
+        assert!(Arc::ptr_eq(&resumed, &shared));
+        assert!(sessions.owns(token, 2));
+        assert!(!sessions.owns(token, 1));
+        // The old owner leaving does not detach the session from its new owner
+        sessions.detach(token, 1);
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(sessions.owns(token, 2));
+    }
+
+    #[test]
+    fn sessions_without_a_board_are_dropped_on_detach() {
+        let sessions = Sessions::new(Duration::from_secs(60));
+        let token = sessions.open(1, SharedGame::default());
+        sessions.detach(token, 1);
+        assert!(sessions.resume(token, 2).is_none());
+    }
+
+    #[test]
+    fn oldest_detached_sessions_are_evicted() {
+        let mut sessions = Sessions::new(Duration::from_secs(60));
+        sessions.max_detached_cells = 300;
+        let live = sessions.open(0, game(100));
+        let tokens: Vec<u64> = (1..=3)
+            .map(|owner| sessions.open(owner, game(10)))
+            .collect();
+        for (owner, token) in (1..=3).zip(&tokens) {
+            sessions.detach(*token, owner);
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        // Another 200 detached cells make room by dropping the two sessions that left first
+        let big = sessions.open(4, game(20));
+        sessions.detach(big, 4);
+        assert!(sessions.resume(tokens[0], 5).is_none());
+        assert!(sessions.resume(tokens[1], 5).is_none());
+        assert!(sessions.resume(tokens[2], 6).is_some());
+        assert!(sessions.resume(big, 7).is_some());
+        assert!(sessions.owns(live, 0));
+    }
+}
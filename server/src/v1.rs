@@ -0,0 +1,42 @@
+//! Translates protocol v1 messages to and from the v3 messages handled by ClientHandler
+
+use protocol::{v1, v3, ErrorCode};
+
+/// Converts a v1 request into its v3 equivalent
+pub fn upgrade(msg: v1::ClientMsg) -> v3::ClientMsg {
+    match msg {
+        v1::ClientMsg::Error(_) => v3::ClientMsg::Error(ErrorCode::Unrecoverable),
+        // Values too large for v3 saturate so they are rejected like any other oversized request
+        v1::ClientMsg::Connect((width, height), mine_count) => v3::ClientMsg::NewGame(
+            u32::try_from(width).unwrap_or(u32::MAX),
+            u32::try_from(height).unwrap_or(u32::MAX),
+            u32::try_from(mine_count).unwrap_or(u32::MAX),
+            v3::GameOptions::default(),
+        ),
+        v1::ClientMsg::Reveal(index) => {
+            v3::ClientMsg::Reveal(u32::try_from(index).unwrap_or(u32::MAX))
+        }
+    }
+}
+
+/// Converts a v3 reply into its v1 equivalent
+pub fn downgrade(msg: v3::ServerMsg) -> v1::ServerMsg {
+    match msg {
+        v3::ServerMsg::Error(code) => v1::ServerMsg::Error(code.to_string()),
+        v3::ServerMsg::Accepted() | v3::ServerMsg::GameCreated(_) => {
+            v1::ServerMsg::ConnectionAccepted
+        }
+        v3::ServerMsg::RevealCells(cells) => v1::ServerMsg::RevealCells(cells.iter().collect()),
+        v3::ServerMsg::GameWin(time, cells) => v1::ServerMsg::GameWin(time, cells.iter().collect()),
+        v3::ServerMsg::GameLoss(time, mines) => {
+            v1::ServerMsg::GameLoss(time, mines.into_iter().map(|i| i as usize).collect())
+        }
+        v3::ServerMsg::Time(_)
+        | v3::ServerMsg::Versions(_)
+        | v3::ServerMsg::MinesRemaining(_)
+        | v3::ServerMsg::SessionAccepted(_)
+        | v3::ServerMsg::Resumed(_) => {
+            v1::ServerMsg::Error(String::from("Reply not supported by protocol v1"))
+        }
+    }
+}
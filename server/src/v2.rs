@@ -0,0 +1,44 @@
+//! Translates protocol v2 messages to and from the v3 messages handled by ClientHandler
+
+use protocol::{v2, v3, ErrorCode};
+
+/// Converts a v2 request into its v3 equivalent
+pub fn upgrade(msg: v2::ClientMsg) -> v3::ClientMsg {
+    match msg {
+        v2::ClientMsg::Error(code) => v3::ClientMsg::Error(code),
+        v2::ClientMsg::SetVersion(version) => v3::ClientMsg::SetVersion(version),
+        v2::ClientMsg::NewGame(width, height, mine_count, options) => {
+            v3::ClientMsg::NewGame(width as u32, height as u32, mine_count as u32, options)
+        }
+        v2::ClientMsg::Reveal(index) => v3::ClientMsg::Reveal(index as u32),
+        v2::ClientMsg::GetTime() => v3::ClientMsg::GetTime(),
+        v2::ClientMsg::CloseGame() => v3::ClientMsg::CloseGame(),
+        v2::ClientMsg::GetVersions() => v3::ClientMsg::GetVersions(),
+        v2::ClientMsg::Chord(index) => v3::ClientMsg::Chord(index as u32),
+        v2::ClientMsg::Flag(index) => v3::ClientMsg::Flag(index as u32),
+        v2::ClientMsg::Unflag(index) => v3::ClientMsg::Unflag(index as u32),
+        v2::ClientMsg::Mark(index) => v3::ClientMsg::Mark(index as u32),
+    }
+}
+
+/// Converts a v3 reply into its v2 equivalent
+///
+/// v2 connections are held to v2::MAX_DIM so every index fits in a u16
+pub fn downgrade(msg: v3::ServerMsg) -> v2::ServerMsg {
+    match msg {
+        v3::ServerMsg::Error(code) => v2::ServerMsg::Error(code),
+        v3::ServerMsg::Accepted() => v2::ServerMsg::Accepted(),
+        v3::ServerMsg::RevealCells(cells) => v2::ServerMsg::RevealCells(cells),
+        v3::ServerMsg::GameWin(time, cells) => v2::ServerMsg::GameWin(time, cells),
+        v3::ServerMsg::GameLoss(time, mines) => {
+            v2::ServerMsg::GameLoss(time, mines.into_iter().map(|i| i as u16).collect())
+        }
+        v3::ServerMsg::Time(time) => v2::ServerMsg::Time(time),
+        v3::ServerMsg::Versions(versions) => v2::ServerMsg::Versions(versions),
+        v3::ServerMsg::GameCreated(seed) => v2::ServerMsg::GameCreated(seed),
+        v3::ServerMsg::MinesRemaining(remaining) => v2::ServerMsg::MinesRemaining(remaining),
+        v3::ServerMsg::SessionAccepted(token) => v2::ServerMsg::SessionAccepted(token),
+        // Resume is only part of v3 so a v2 session never gets this reply
+        v3::ServerMsg::Resumed(_) => v2::ServerMsg::Error(ErrorCode::RecipientUnrecoverable),
+    }
+}
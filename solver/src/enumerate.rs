@@ -0,0 +1,246 @@
+//! Splits the frontier into independent groups and counts the mine layouts of each
+
+use crate::Constraint;
+use std::collections::HashMap;
+
+/// Most layouts tried across all groups before the remaining groups are estimated
+const SEARCH_BUDGET: usize = 1 << 22;
+/// Largest group that is enumerated, larger ones are estimated right away
+const MAX_GROUP_CELLS: usize = 256;
+
+/// Cells sharing constraints, with how their mines can be laid out
+pub struct Group {
+    pub cells: Vec<usize>,
+    /// Relative number of layouts placing k mines in the group, indexed by k
+    pub counts: Vec<f64>,
+    /// For every cell, the relative number of those layouts that put a mine on it
+    pub cell_counts: Vec<Vec<f64>>,
+    /// False if the counts are estimates because the group took too long to enumerate
+    pub exact: bool,
+}
+
+impl Group {
+    /// Whether a cell is a mine in every layout or in none, None if the layouts disagree
+    ///
+    /// Only meaningful for exact groups, it does not take the other groups into account.
+    pub fn certain(&self, cell: usize) -> Option<bool> {
+        let counts = &self.cell_counts[cell];
+        if counts.iter().all(|&c| c == 0.0) {
+            Some(false)
+        } else if counts.iter().zip(&self.counts).all(|(c, n)| c == n) {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+/// Splits the constraints into groups and counts the layouts of each, none may hold more than `mines_left`
+///
+/// Also returns how many layouts were tried.
+pub fn groups(constraints: Vec<Constraint>, mines_left: usize) -> (Vec<Group>, usize) {
+    let mut budget = SEARCH_BUDGET;
+    let groups = split(constraints)
+        .into_iter()
+        .map(|(cells, constraints)| {
+            if cells.len() > MAX_GROUP_CELLS {
+                return estimate(cells, &constraints, mines_left);
+            }
+            let mut search = Search::new(&cells, &constraints, mines_left);
+            if search.run(&mut budget) {
+                search.into_group(cells)
+            } else {
+                estimate(cells, &constraints, mines_left)
+            }
+        })
+        .collect();
+    (groups, SEARCH_BUDGET - budget)
+}
+
+/// Groups constraints which share cells, directly or through other constraints
+fn split(constraints: Vec<Constraint>) -> Vec<(Vec<usize>, Vec<Constraint>)> {
+    // Union find over the constraints, joined through the cells they share
+    let mut parent: Vec<usize> = (0..constraints.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    let mut owner: HashMap<usize, usize> = HashMap::new();
+    for (i, constraint) in constraints.iter().enumerate() {
+        for cell in &constraint.cells {
+            let other = *owner.entry(*cell).or_insert(i);
+            let (a, b) = (root(&mut parent, i), root(&mut parent, other));
+            parent[a] = b;
+        }
+    }
+
+    let mut groups: HashMap<usize, (Vec<usize>, Vec<Constraint>)> = HashMap::new();
+    for (i, constraint) in constraints.into_iter().enumerate() {
+        let group = groups.entry(root(&mut parent, i)).or_default();
+        group.0.extend(&constraint.cells);
+        group.1.push(constraint);
+    }
+    let mut groups: Vec<_> = groups.into_values().collect();
+    for (cells, _) in groups.iter_mut() {
+        cells.sort_unstable();
+        cells.dedup();
+    }
+    // Keeps the order, and so the search budget spent on each group, independent of hashing
+    groups.sort_unstable_by_key(|(cells, _)| cells[0]);
+    groups
+}
+
+/// Backtracking search over the mine layouts of a group
+struct Search {
+    /// Constraints each cell of the group takes part in, cells are numbered within the group
+    cell_constraints: Vec<Vec<usize>>,
+    /// Mines each constraint needs
+    needed: Vec<usize>,
+    /// Mines placed on each constraint's cells so far
+    placed: Vec<usize>,
+    /// Cells of each constraint without a value yet
+    open: Vec<usize>,
+    mines: Vec<bool>,
+    mines_left: usize,
+    placed_total: usize,
+    counts: Vec<f64>,
+    cell_counts: Vec<Vec<f64>>,
+}
+impl Search {
+    fn new(cells: &[usize], constraints: &[Constraint], mines_left: usize) -> Self {
+        let local: HashMap<usize, usize> = cells.iter().enumerate().map(|(i, c)| (*c, i)).collect();
+        let mut cell_constraints = vec![vec![]; cells.len()];
+        for (i, constraint) in constraints.iter().enumerate() {
+            for cell in &constraint.cells {
+                cell_constraints[local[cell]].push(i);
+            }
+        }
+        let most = cells.len().min(mines_left);
+        Search {
+            cell_constraints,
+            needed: constraints.iter().map(|c| c.mines).collect(),
+            placed: vec![0; constraints.len()],
+            open: constraints.iter().map(|c| c.cells.len()).collect(),
+            mines: vec![false; cells.len()],
+            mines_left,
+            placed_total: 0,
+            counts: vec![0.0; most + 1],
+            cell_counts: vec![vec![0.0; most + 1]; cells.len()],
+        }
+    }
+
+    /// Counts every layout, returns false if the budget ran out first
+    fn run(&mut self, budget: &mut usize) -> bool {
+        self.visit(0, budget)
+    }
+
+    fn visit(&mut self, cell: usize, budget: &mut usize) -> bool {
+        if *budget == 0 {
+            return false;
+        }
+        *budget -= 1;
+        if cell == self.mines.len() {
+            self.record();
+            return true;
+        }
+        for mine in [false, true] {
+            if mine && self.placed_total == self.mines_left {
+                continue;
+            }
+            let fits = self.assign(cell, mine);
+            let finished = !fits || self.visit(cell + 1, budget);
+            self.unassign(cell, mine);
+            if !finished {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Gives a cell a value, returns false if a constraint can no longer be met
+    fn assign(&mut self, cell: usize, mine: bool) -> bool {
+        self.mines[cell] = mine;
+        self.placed_total += mine as usize;
+        let mut fits = true;
+        for &c in &self.cell_constraints[cell] {
+            self.open[c] -= 1;
+            self.placed[c] += mine as usize;
+            fits &=
+                self.placed[c] <= self.needed[c] && self.placed[c] + self.open[c] >= self.needed[c];
+        }
+        fits
+    }
+
+    fn unassign(&mut self, cell: usize, mine: bool) {
+        self.mines[cell] = false;
+        self.placed_total -= mine as usize;
+        for &c in &self.cell_constraints[cell] {
+            self.open[c] += 1;
+            self.placed[c] -= mine as usize;
+        }
+    }
+
+    /// Counts a complete layout
+    fn record(&mut self) {
+        let k = self.placed_total;
+        self.counts[k] += 1.0;
+        for (cell, mine) in self.mines.iter().enumerate() {
+            if *mine {
+                self.cell_counts[cell][k] += 1.0;
+            }
+        }
+    }
+
+    fn into_group(self, cells: Vec<usize>) -> Group {
+        Group {
+            cells,
+            counts: self.counts,
+            cell_counts: self.cell_counts,
+            exact: true,
+        }
+    }
+}
+
+/// Estimates a group too large to enumerate
+///
+/// Each cell gets the average density of the constraints it takes part in and the group is
+/// assumed to always hold the mines those densities add up to.
+fn estimate(cells: Vec<usize>, constraints: &[Constraint], mines_left: usize) -> Group {
+    let mut density: HashMap<usize, (f64, usize)> = HashMap::new();
+    for constraint in constraints {
+        let share = constraint.mines as f64 / constraint.cells.len() as f64;
+        for cell in &constraint.cells {
+            let entry = density.entry(*cell).or_default();
+            entry.0 += share;
+            entry.1 += 1;
+        }
+    }
+    let probabilities: Vec<f64> = cells
+        .iter()
+        .map(|cell| {
+            let (sum, count) = density[cell];
+            sum / count as f64
+        })
+        .collect();
+    let k = (probabilities.iter().sum::<f64>().round() as usize).min(mines_left);
+
+    let mut counts = vec![0.0; k + 1];
+    counts[k] = 1.0;
+    let cell_counts = probabilities
+        .iter()
+        .map(|p| {
+            let mut row = vec![0.0; k + 1];
+            row[k] = *p;
+            row
+        })
+        .collect();
+    Group {
+        cells,
+        counts,
+        cell_counts,
+        exact: false,
+    }
+}
@@ -0,0 +1,521 @@
+//! Works out what can be known about a MineSweeper board from what a player sees
+//!
+//! Every revealed number constrains its hidden neighbours. Cells that can be settled by a
+//! single number are settled first, the rest of the frontier is split into groups of cells
+//! sharing constraints and each group small enough is enumerated exactly. Groups that take
+//! too long are estimated instead. The groups and the cells away from the frontier are then
+//! weighed against each other using the number of mines left.
+//!
+//! Flags are trusted, a flagged cell counts as a mine.
+
+mod enumerate;
+
+use enumerate::Group;
+use std::fmt;
+
+/// What the player sees of a cell
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Tile {
+    Hidden,
+    Flagged,
+    Revealed(u8),
+}
+
+/// Hidden cells around a revealed number together with how many of them are mines
+#[derive(Clone, Debug)]
+struct Constraint {
+    cells: Vec<usize>,
+    mines: usize,
+}
+
+/// What the solver worked out about a board
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    /// Chance of every cell being a mine, None for revealed and flagged cells
+    pub probabilities: Vec<Option<f64>>,
+    /// Hidden cells that are certainly safe, in ascending order
+    pub safe: Vec<usize>,
+    /// Hidden, unflagged cells that are certainly mines, in ascending order
+    pub mines: Vec<usize>,
+    /// False if part of the frontier was too large to enumerate and its probabilities are estimates
+    ///
+    /// Safe cells and mines are certain either way, but fewer are found without an exact analysis.
+    pub exact: bool,
+    /// Steps the analysis took, counting scanned cells, tried layouts and weighing
+    ///
+    /// Lets callers bound their work the same way on every machine, unlike a time limit.
+    pub effort: usize,
+}
+impl Analysis {
+    /// A certainly safe cell if there is one, otherwise the hidden cell least likely to be a mine
+    pub fn best_move(&self) -> Option<usize> {
+        if let Some(&safe) = self.safe.first() {
+            return Some(safe);
+        }
+        self.probabilities
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.map(|p| (i, p)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+}
+
+/// The revealed numbers, flags and mine count can not all be right
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Contradiction;
+impl fmt::Display for Contradiction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The revealed numbers, flags and mine count contradict each other"
+        )
+    }
+}
+impl std::error::Error for Contradiction {}
+
+/// Analyses a board of `width` columns stored row by row, `mines_left` counts the unflagged mines
+pub fn analyse(tiles: &[Tile], width: usize, mines_left: usize) -> Result<Analysis, Contradiction> {
+    assert!(
+        width > 0 && tiles.len().is_multiple_of(width),
+        "Tiles do not form a board of width {}",
+        width
+    );
+    let height = tiles.len() / width;
+
+    // Some(true) for cells known to be mines and Some(false) for cells known to be safe
+    let mut known: Vec<Option<bool>> = vec![None; tiles.len()];
+    let mut constraints = constraints(tiles, width, height)?;
+    let known_mines = settle(&mut constraints, &mut known)?;
+    let mines_left = mines_left.checked_sub(known_mines).ok_or(Contradiction)?;
+
+    let (groups, searched) = enumerate::groups(constraints, mines_left);
+    let exact = groups.iter().all(|g| g.exact);
+
+    let mut on_frontier = vec![false; tiles.len()];
+    for cell in groups.iter().flat_map(|g| &g.cells) {
+        on_frontier[*cell] = true;
+    }
+    let interior: Vec<usize> = (0..tiles.len())
+        .filter(|&i| tiles[i] == Tile::Hidden && known[i].is_none() && !on_frontier[i])
+        .collect();
+
+    let mut probabilities: Vec<Option<f64>> = tiles
+        .iter()
+        .zip(&known)
+        .map(|(tile, known)| match (tile, known) {
+            (Tile::Hidden, Some(true)) => Some(1.0),
+            (Tile::Hidden, Some(false)) => Some(0.0),
+            _ => None,
+        })
+        .collect();
+    let weighed = weigh(&groups, interior.len(), mines_left)?;
+    let weighed_steps = weighed.steps;
+    for (group, cells) in groups.iter().zip(weighed.groups) {
+        for (cell, p) in group.cells.iter().zip(cells) {
+            probabilities[*cell] = Some(p);
+        }
+    }
+    for cell in interior {
+        probabilities[cell] = Some(weighed.interior);
+    }
+
+    // Estimated groups are forced to hold a set number of mines, so once one was estimated
+    // the mine count can no longer settle anything and only the numbers are trusted
+    if exact {
+        for (i, p) in probabilities.iter().enumerate() {
+            if tiles[i] == Tile::Hidden && known[i].is_none() {
+                known[i] = p.filter(|p| *p == 0.0 || *p == 1.0).map(|p| p == 1.0);
+            }
+        }
+    } else {
+        for group in groups.iter().filter(|g| g.exact) {
+            for (i, cell) in group.cells.iter().enumerate() {
+                known[*cell] = group.certain(i);
+            }
+        }
+    }
+    let certain = |value: bool| -> Vec<usize> {
+        (0..tiles.len())
+            .filter(|&i| tiles[i] == Tile::Hidden && known[i] == Some(value))
+            .collect()
+    };
+    Ok(Analysis {
+        safe: certain(false),
+        mines: certain(true),
+        probabilities,
+        exact,
+        effort: tiles.len() + searched + weighed_steps,
+    })
+}
+
+/// Indices of the up to 8 cells surrounding a cell
+fn neighbours(index: usize, width: usize, height: usize) -> impl Iterator<Item = usize> {
+    let (x, y) = (index % width, index / width);
+    let xs = x.saturating_sub(1)..=(x + 1).min(width - 1);
+    (y.saturating_sub(1)..=(y + 1).min(height - 1))
+        .flat_map(move |ny| xs.clone().map(move |nx| nx + ny * width))
+        .filter(move |&n| n != index)
+}
+
+/// Builds a constraint from every revealed number that has hidden neighbours
+fn constraints(
+    tiles: &[Tile],
+    width: usize,
+    height: usize,
+) -> Result<Vec<Constraint>, Contradiction> {
+    let mut constraints = vec![];
+    for (index, tile) in tiles.iter().enumerate() {
+        let Tile::Revealed(number) = *tile else {
+            continue;
+        };
+        let mut cells = vec![];
+        let mut flags = 0;
+        for n in neighbours(index, width, height) {
+            match tiles[n] {
+                Tile::Hidden => cells.push(n),
+                Tile::Flagged => flags += 1,
+                Tile::Revealed(_) => (),
+            }
+        }
+        let mines = (number as usize)
+            .checked_sub(flags)
+            .filter(|&mines| mines <= cells.len())
+            .ok_or(Contradiction)?;
+        if !cells.is_empty() {
+            constraints.push(Constraint { cells, mines });
+        }
+    }
+    Ok(constraints)
+}
+
+/// Settles every cell a single constraint decides, returns how many mines were found
+///
+/// Settled cells are removed from the constraints and constraints left without cells are dropped.
+fn settle(
+    constraints: &mut Vec<Constraint>,
+    known: &mut [Option<bool>],
+) -> Result<usize, Contradiction> {
+    let mut mines_found = 0;
+    loop {
+        let mut progress = false;
+        for constraint in constraints.iter_mut() {
+            let mut mines = constraint.mines;
+            constraint.cells.retain(|&cell| match known[cell] {
+                Some(true) => {
+                    // Checked below, the count may not drop below zero
+                    mines = mines.wrapping_sub(1);
+                    false
+                }
+                Some(false) => false,
+                None => true,
+            });
+            if mines > constraint.cells.len() {
+                return Err(Contradiction);
+            }
+            constraint.mines = mines;
+
+            let value = if mines == 0 {
+                false
+            } else if mines == constraint.cells.len() {
+                true
+            } else {
+                continue;
+            };
+            for &cell in &constraint.cells {
+                if known[cell].is_none() {
+                    known[cell] = Some(value);
+                    mines_found += value as usize;
+                    progress = true;
+                }
+            }
+        }
+        constraints.retain(|c| !c.cells.is_empty());
+        if !progress {
+            return Ok(mines_found);
+        }
+    }
+}
+
+/// Mine probabilities once every group has been weighed against the others
+struct Weighed {
+    /// Probability of each cell of each group, in the order of the group's cells
+    groups: Vec<Vec<f64>>,
+    /// Probability shared by every hidden cell away from the frontier
+    interior: f64,
+    /// Multiplications spent combining the groups
+    steps: usize,
+}
+
+/// Combines the groups with the cells away from the frontier using the number of mines left
+///
+/// A layout putting k mines on the frontier is as likely as the number of ways to place
+/// the other mines_left - k mines among the interior cells.
+fn weigh(groups: &[Group], interior: usize, mines_left: usize) -> Result<Weighed, Contradiction> {
+    // Most mines the frontier can hold, bounding the interior mine counts that matter
+    let frontier_max: usize = groups.iter().map(|g| g.counts.len() - 1).sum();
+    let interior_weight = interior_weights(interior, mines_left, frontier_max);
+    let weight = |frontier_mines: usize| -> f64 {
+        mines_left
+            .checked_sub(frontier_mines)
+            .map_or(0.0, &interior_weight)
+    };
+
+    // Mines placed by all groups before and after each group
+    let mut steps = 0;
+    let mut prefix = vec![vec![1.0]];
+    for group in groups {
+        let last = prefix.last().expect("prefix starts with one entry");
+        steps += last.len() * group.counts.len();
+        prefix.push(convolve(last, &group.counts));
+    }
+    let mut suffix = vec![vec![1.0]];
+    for group in groups.iter().rev() {
+        let last = suffix.last().expect("suffix starts with one entry");
+        steps += last.len() * group.counts.len();
+        suffix.push(convolve(last, &group.counts));
+    }
+    suffix.reverse();
+
+    let mut weighed = Vec::with_capacity(groups.len());
+    for (i, group) in groups.iter().enumerate() {
+        let others = convolve(&prefix[i], &suffix[i + 1]);
+        steps += prefix[i].len() * suffix[i + 1].len()
+            + (group.cell_counts.len() + 1) * group.counts.len() * others.len();
+        // Weight of the group holding k mines given every way the others and the interior can go
+        let rest: Vec<f64> = (0..group.counts.len())
+            .map(|k| {
+                others
+                    .iter()
+                    .enumerate()
+                    .map(|(m, w)| w * weight(k + m))
+                    .sum()
+            })
+            .collect();
+        let total: f64 = group.counts.iter().zip(&rest).map(|(c, r)| c * r).sum();
+        if total <= 0.0 {
+            return Err(Contradiction);
+        }
+        weighed.push(
+            group
+                .cell_counts
+                .iter()
+                .map(|counts| counts.iter().zip(&rest).map(|(c, r)| c * r).sum::<f64>() / total)
+                .collect(),
+        );
+    }
+
+    let all = prefix.last().expect("prefix starts with one entry");
+    let total: f64 = all.iter().enumerate().map(|(m, w)| w * weight(m)).sum();
+    if total <= 0.0 {
+        return Err(Contradiction);
+    }
+    let interior_mines: f64 = all
+        .iter()
+        .enumerate()
+        .filter(|(m, _)| *m <= mines_left)
+        .map(|(m, w)| w * weight(m) * (mines_left - m) as f64)
+        .sum();
+    let interior = if interior == 0 {
+        0.0
+    } else {
+        interior_mines / total / interior as f64
+    };
+    Ok(Weighed {
+        groups: weighed,
+        interior,
+        steps,
+    })
+}
+
+/// Relative number of ways to place r mines among n interior cells, for the r that can occur
+///
+/// Binomials get far too large for floats on big boards, so they are scaled by the smallest
+/// one that can occur and built up from the ratio of neighbouring binomials.
+fn interior_weights(n: usize, mines_left: usize, frontier_max: usize) -> impl Fn(usize) -> f64 {
+    let low = mines_left.saturating_sub(frontier_max);
+    let high = mines_left.min(n);
+    let mut logs = vec![];
+    let mut log = 0.0;
+    for r in low..=high {
+        logs.push(log);
+        log += ((n - r) as f64 / (r + 1) as f64).ln();
+    }
+    let max = logs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let weights: Vec<f64> = logs.iter().map(|l| (l - max).exp()).collect();
+    move |r| {
+        r.checked_sub(low)
+            .and_then(|i| weights.get(i).copied())
+            .unwrap_or(0.0)
+    }
+}
+
+/// Combines two distributions of mine counts, scaled so the largest entry is 1
+fn convolve(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut out = vec![0.0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            out[i + j] += x * y;
+        }
+    }
+    let max = out.iter().copied().fold(0.0, f64::max);
+    if max > 0.0 {
+        out.iter_mut().for_each(|w| *w /= max);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a board drawn with `*` for a hidden mine, `#` for a hidden safe cell,
+    /// `F` for a flagged mine and `o` for a revealed cell, whose number is worked out
+    ///
+    /// Returns the tiles, the width and the number of unflagged mines.
+    fn board(rows: &[&str]) -> (Vec<Tile>, usize, usize) {
+        let width = rows[0].len();
+        let cells: Vec<u8> = rows.iter().flat_map(|row| row.bytes()).collect();
+        let height = cells.len() / width;
+        let tiles = (0..cells.len())
+            .map(|i| match cells[i] {
+                b'#' | b'*' => Tile::Hidden,
+                b'F' => Tile::Flagged,
+                _ => {
+                    let mines = neighbours(i, width, height)
+                        .filter(|&n| matches!(cells[n], b'*' | b'F'))
+                        .count();
+                    Tile::Revealed(mines as u8)
+                }
+            })
+            .collect();
+        let mines_left = cells.iter().filter(|&&c| c == b'*').count();
+        (tiles, width, mines_left)
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} is not {}", a, b);
+    }
+
+    #[test]
+    fn one_two_one() {
+        let (tiles, width, mines_left) = board(&["ooo", "*#*"]);
+        let analysis = analyse(&tiles, width, mines_left).unwrap();
+        assert_eq!(analysis.safe, vec![4]);
+        assert_eq!(analysis.mines, vec![3, 5]);
+        assert_eq!(analysis.best_move(), Some(4));
+        assert!(analysis.exact);
+    }
+
+    #[test]
+    fn numbers_that_can_not_be_met() {
+        // A 2 with a single hidden neighbour
+        let tiles = [Tile::Revealed(2), Tile::Hidden];
+        assert_eq!(analyse(&tiles, 2, 1).unwrap_err(), Contradiction);
+        // More flags around a number than it allows
+        let tiles = [Tile::Flagged, Tile::Revealed(0), Tile::Hidden];
+        assert_eq!(analyse(&tiles, 3, 1).unwrap_err(), Contradiction);
+    }
+
+    #[test]
+    fn mine_count_that_can_not_be_met() {
+        // Every hidden cell touches the 1 so exactly one mine fits
+        let tiles = [Tile::Revealed(1), Tile::Hidden, Tile::Hidden, Tile::Hidden];
+        assert!(analyse(&tiles, 2, 1).is_ok());
+        assert_eq!(analyse(&tiles, 2, 2).unwrap_err(), Contradiction);
+        assert_eq!(analyse(&tiles, 2, 0).unwrap_err(), Contradiction);
+    }
+
+    #[test]
+    fn layouts_are_weighed_by_the_interior() {
+        // B alone or A and C satisfy both 1s, the three interior cells take the other mines
+        let (tiles, width, _) = board(&["#o*o####"]);
+        let p = analyse(&tiles, width, 2).unwrap().probabilities;
+        // One mine on the frontier leaves 3 ways for the interior, two mines leave 1
+        assert_close(p[0].unwrap(), 0.25);
+        assert_close(p[2].unwrap(), 0.75);
+        assert_close(p[4].unwrap(), 0.25);
+        for interior in &p[5..] {
+            assert_close(interior.unwrap(), 0.25);
+        }
+        assert_eq!(p[1], None);
+    }
+
+    #[test]
+    fn large_groups_are_estimated() {
+        // A chain of 1s between hidden cells forms a single group too large to enumerate
+        let row: String = (0..601)
+            .map(|i| {
+                if i % 4 == 0 {
+                    '*'
+                } else if i % 2 == 0 {
+                    '#'
+                } else {
+                    'o'
+                }
+            })
+            .collect();
+        let (tiles, width, mines_left) = board(&[&row]);
+        let analysis = analyse(&tiles, width, mines_left).unwrap();
+        assert!(!analysis.exact);
+        assert!(analysis.safe.is_empty() && analysis.mines.is_empty());
+        assert!(analysis
+            .probabilities
+            .iter()
+            .flatten()
+            .all(|p| (0.0..=1.0).contains(p)));
+    }
+
+    #[test]
+    fn estimates_are_not_certain() {
+        // The estimate puts 151 mines on the frontier of the chain, leaving none for the row
+        // below it, yet mines on every other hidden cell of the chain meet the 1s with 150
+        let chain: String = (0..601)
+            .map(|i| match i % 4 {
+                0 => '*',
+                2 => '#',
+                _ => 'o',
+            })
+            .collect();
+        let hidden = "#".repeat(601);
+        let (tiles, width, mines_left) = board(&[&chain, &hidden, &hidden]);
+        let analysis = analyse(&tiles, width, mines_left).unwrap();
+        assert_eq!(mines_left, 151);
+        assert!(!analysis.exact);
+        assert!(analysis.safe.is_empty() && analysis.mines.is_empty());
+    }
+
+    #[test]
+    fn probabilities_add_up_to_the_mines() {
+        let rows = [
+            "#*#o*##", //
+            "##ooo##", //
+            "oooooF#", //
+            "*#oo#*#", //
+            "##oo###", //
+            "#*oo*##", //
+        ];
+        let (tiles, width, mines_left) = board(&rows);
+        let analysis = analyse(&tiles, width, mines_left).unwrap();
+        assert!(analysis.exact);
+        let p = &analysis.probabilities;
+        let height = tiles.len() / width;
+
+        // Every layout meets every number so the expected mines around one match it exactly
+        for (i, tile) in tiles.iter().enumerate() {
+            let Tile::Revealed(number) = *tile else {
+                continue;
+            };
+            let flags = neighbours(i, width, height)
+                .filter(|&n| tiles[n] == Tile::Flagged)
+                .count();
+            let expected: f64 = neighbours(i, width, height).filter_map(|n| p[n]).sum();
+            assert_close(expected, (number as usize - flags) as f64);
+        }
+        assert_close(p.iter().flatten().sum(), mines_left as f64);
+        assert!(p.iter().flatten().all(|p| (0.0..=1.0).contains(p)));
+        for (i, tile) in tiles.iter().enumerate() {
+            assert_eq!(p[i].is_some(), *tile == Tile::Hidden);
+        }
+    }
+}
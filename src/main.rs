@@ -1 +0,0 @@
-fn main() {}